@@ -0,0 +1,57 @@
+use std::collections::{BTreeMap, HashMap};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use memuse::DynamicUsage;
+
+const ENTRIES: &[u64] = &[1_000, 1_000_000];
+
+fn bench_vec(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Vec");
+    for &entries in ENTRIES {
+        let copy: Vec<u64> = (0..entries).collect();
+        group.bench_with_input(BenchmarkId::new("u64", entries), &copy, |b, v| {
+            b.iter(|| v.dynamic_usage())
+        });
+
+        let allocating: Vec<String> = (0..entries).map(|i| i.to_string()).collect();
+        group.bench_with_input(BenchmarkId::new("String", entries), &allocating, |b, v| {
+            b.iter(|| v.dynamic_usage())
+        });
+    }
+    group.finish();
+}
+
+fn bench_hashmap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("HashMap");
+    for &entries in ENTRIES {
+        let copy: HashMap<u64, u64> = (0..entries).map(|i| (i, i)).collect();
+        group.bench_with_input(BenchmarkId::new("u64", entries), &copy, |b, m| {
+            b.iter(|| m.dynamic_usage())
+        });
+
+        let allocating: HashMap<u64, String> = (0..entries).map(|i| (i, i.to_string())).collect();
+        group.bench_with_input(BenchmarkId::new("String", entries), &allocating, |b, m| {
+            b.iter(|| m.dynamic_usage())
+        });
+    }
+    group.finish();
+}
+
+fn bench_btreemap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("BTreeMap");
+    for &entries in ENTRIES {
+        let copy: BTreeMap<u64, u64> = (0..entries).map(|i| (i, i)).collect();
+        group.bench_with_input(BenchmarkId::new("u64", entries), &copy, |b, m| {
+            b.iter(|| m.dynamic_usage())
+        });
+
+        let allocating: BTreeMap<u64, String> = (0..entries).map(|i| (i, i.to_string())).collect();
+        group.bench_with_input(BenchmarkId::new("String", entries), &allocating, |b, m| {
+            b.iter(|| m.dynamic_usage())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_vec, bench_hashmap, bench_btreemap);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+fn main() {
+    // The `allocator_api` feature flag relies on an unstable Rust feature. We only enable
+    // it on compilers that support it, so that `--all-features` works on stable Rust.
+    println!("cargo:rustc-check-cfg=cfg(memuse_allocator_api)");
+    if std::env::var_os("CARGO_FEATURE_ALLOCATOR_API").is_some()
+        && version_check::is_feature_flaggable() == Some(true)
+    {
+        println!("cargo:rustc-cfg=memuse_allocator_api");
+    }
+
+    // Since Rust 1.67, `VecDeque` no longer leaves a slot in its ring buffer empty.
+    println!("cargo:rustc-check-cfg=cfg(memuse_vecdeque_no_empty_slot)");
+    if version_check::is_min_version("1.67.0") == Some(true) {
+        println!("cargo:rustc-cfg=memuse_vecdeque_no_empty_slot");
+    }
+}
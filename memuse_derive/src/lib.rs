@@ -0,0 +1,220 @@
+//! Derive macro for the `memuse::DynamicUsage` trait.
+//!
+//! This crate is re-exported by `memuse` behind its `derive` feature flag; use it
+//! through `memuse::DynamicUsage` rather than depending on it directly.
+
+#![forbid(unsafe_code)]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Field, Fields, GenericParam, Generics,
+};
+
+/// Derives `memuse::DynamicUsage` for a struct or enum.
+///
+/// The dynamic memory usage of a struct is the sum of the dynamic memory usage of its
+/// fields, and the dynamic memory usage of an enum is that of the fields of its active
+/// variant. Every type parameter is required to implement `DynamicUsage`.
+///
+/// Fields marked with `#[memuse(skip)]` are not measured, and their types don't need to
+/// implement `DynamicUsage`. This is useful for borrowed fields, whose target is owned
+/// (and measured) elsewhere.
+#[proc_macro_derive(DynamicUsage, attributes(memuse))]
+pub fn derive_dynamic_usage(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = add_trait_bounds(input.generics.clone());
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let arms: Vec<(TokenStream2, Vec<Ident>)> = match &input.data {
+        Data::Struct(data) => vec![destructure(quote!(#name), &data.fields)?],
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                let ident = &variant.ident;
+                destructure(quote!(#name::#ident), &variant.fields)
+            })
+            .collect::<syn::Result<_>>()?,
+        Data::Union(data) => {
+            return Err(syn::Error::new_spanned(
+                data.union_token,
+                "DynamicUsage cannot be derived for unions",
+            ))
+        }
+    };
+
+    // Types without any fields can't allocate.
+    if arms.iter().all(|(_, bindings)| bindings.is_empty()) {
+        return Ok(quote! {
+            impl #impl_generics ::memuse::DynamicUsage for #name #ty_generics #where_clause {
+                #[inline(always)]
+                fn dynamic_usage(&self) -> usize {
+                    0
+                }
+
+                #[inline(always)]
+                fn dynamic_usage_bounds(&self) -> (usize, ::core::option::Option<usize>) {
+                    (0, ::core::option::Option::Some(0))
+                }
+            }
+        });
+    }
+
+    let usage = match_arms(
+        &arms,
+        |fields| quote!(0 #(+ ::memuse::DynamicUsage::dynamic_usage(#fields))*),
+    );
+    let bounds = match_arms(&arms, |fields| {
+        quote! {
+            let bounds = (0, ::core::option::Option::Some(0));
+            #(
+                let bounds = {
+                    let (lower, upper) = ::memuse::DynamicUsage::dynamic_usage_bounds(#fields);
+                    (bounds.0 + lower, bounds.1.zip(upper).map(|(a, b)| a + b))
+                };
+            )*
+            bounds
+        }
+    });
+    let try_usage = match_arms(&arms, |fields| {
+        quote! {
+            ::core::option::Option::Some(
+                0 #(+ ::memuse::DynamicUsage::try_dynamic_usage(#fields)?)*
+            )
+        }
+    });
+    let may_overcount = match_arms(
+        &arms,
+        |fields| quote!(false #(|| ::memuse::DynamicUsage::may_overcount(#fields))*),
+    );
+    let used_usage = match_arms(
+        &arms,
+        |fields| quote!(0 #(+ ::memuse::DynamicUsage::dynamic_usage_used(#fields))*),
+    );
+    let dedup_usage = match_arms(
+        &arms,
+        |fields| quote!(0 #(+ ::memuse::DynamicUsage::dedup_dynamic_usage(#fields, visited))*),
+    );
+
+    Ok(quote! {
+        impl #impl_generics ::memuse::DynamicUsage for #name #ty_generics #where_clause {
+            fn dynamic_usage(&self) -> usize {
+                #usage
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, ::core::option::Option<usize>) {
+                #bounds
+            }
+
+            fn try_dynamic_usage(&self) -> ::core::option::Option<usize> {
+                #try_usage
+            }
+
+            fn may_overcount(&self) -> bool {
+                #may_overcount
+            }
+
+            fn dedup_dynamic_usage(&self, visited: &mut ::memuse::Visited) -> usize {
+                #dedup_usage
+            }
+
+            fn dynamic_usage_used(&self) -> usize {
+                #used_usage
+            }
+        }
+    })
+}
+
+/// Adds a `DynamicUsage` bound to every type parameter.
+fn add_trait_bounds(mut generics: Generics) -> Generics {
+    for param in &mut generics.params {
+        if let GenericParam::Type(param) = param {
+            param.bounds.push(parse_quote!(::memuse::DynamicUsage));
+        }
+    }
+    generics
+}
+
+/// Returns `true` if the field is marked with `#[memuse(skip)]`.
+fn is_skipped(field: &Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("memuse"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported memuse attribute"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+/// Returns a pattern that binds each of the given fields by reference, along with the
+/// bindings. Skipped fields are matched but not bound.
+fn destructure(path: TokenStream2, fields: &Fields) -> syn::Result<(TokenStream2, Vec<Ident>)> {
+    match fields {
+        Fields::Named(fields) => {
+            let mut patterns = vec![];
+            let mut bindings = vec![];
+            for field in &fields.named {
+                let name = field.ident.as_ref().expect("named field");
+                if is_skipped(field)? {
+                    patterns.push(quote!(#name: _));
+                } else {
+                    let binding = format_ident!("__{}", name.unraw());
+                    patterns.push(quote!(#name: #binding));
+                    bindings.push(binding);
+                }
+            }
+            Ok((quote!(#path { #(#patterns),* }), bindings))
+        }
+        Fields::Unnamed(fields) => {
+            let mut patterns = vec![];
+            let mut bindings = vec![];
+            for (i, field) in fields.unnamed.iter().enumerate() {
+                if is_skipped(field)? {
+                    patterns.push(quote!(_));
+                } else {
+                    let binding = Ident::new(&format!("__field{}", i), Span::call_site());
+                    patterns.push(quote!(#binding));
+                    bindings.push(binding);
+                }
+            }
+            Ok((quote!(#path(#(#patterns),*)), bindings))
+        }
+        Fields::Unit => Ok((quote!(#path), vec![])),
+    }
+}
+
+/// Builds a `match` over `self` that evaluates `body` with the fields of each arm.
+fn match_arms(
+    arms: &[(TokenStream2, Vec<Ident>)],
+    body: impl Fn(&[Ident]) -> TokenStream2,
+) -> TokenStream2 {
+    let arms = arms.iter().map(|(pattern, bindings)| {
+        let body = body(bindings);
+        quote!(#pattern => { #body })
+    });
+    quote! {
+        match self {
+            #(#arms)*
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! `DynamicUsage` impls for `BTreeMap` and `BTreeSet`.
+//!
+//! The node layout of Rust's `BTreeMap` is private, so we mirror it here in order to
+//! compute its size.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::mem::{self, MaybeUninit};
+use core::ptr::NonNull;
+
+use crate::{size_histogram, BTreeUsage, ContainerUsage, DynamicUsage, MapUsage, Visited};
+
+#[cfg(feature = "std")]
+use crate::write_map_usage_report;
+
+// The node layout is sourced from here:
+//   https://github.com/rust-lang/rust/blob/1.66.0/library/alloc/src/collections/btree/node.rs
+
+const B: usize = 6;
+pub(crate) const CAPACITY: usize = 2 * B - 1;
+
+#[allow(dead_code)]
+#[repr(C)]
+struct LeafNode<K, V> {
+    parent: Option<NonNull<InternalNode<K, V>>>,
+    parent_idx: MaybeUninit<u16>,
+    len: u16,
+    keys: [MaybeUninit<K>; CAPACITY],
+    vals: [MaybeUninit<V>; CAPACITY],
+}
+
+#[allow(dead_code)]
+#[repr(C)]
+struct InternalNode<K, V> {
+    data: LeafNode<K, V>,
+    edges: [MaybeUninit<NonNull<LeafNode<K, V>>>; 2 * B],
+}
+
+/// Returns the sizes of the leaf and internal nodes of a `BTreeMap<K, V>`.
+pub(crate) fn node_sizes<K, V>() -> (usize, usize) {
+    (
+        mem::size_of::<LeafNode<K, V>>(),
+        mem::size_of::<InternalNode<K, V>>(),
+    )
+}
+
+/// Returns the lower and upper bounds on the height of a B-tree containing `entries`
+/// entries, where a tree with a single leaf node has height 0.
+fn btree_height_bounds(entries: usize) -> (usize, usize) {
+    if entries == 0 {
+        return (0, 0);
+    }
+
+    // Every node can hold at most CAPACITY entries and 2 * B children. Every node except
+    // the root must hold at least B - 1 entries, and the root must hold at least one.
+    // This bounds the height of the tree:
+    //
+    //     (2 * B)^(h + 1) - 1 >= entries >= 2 * B^h - 1
+    //
+    // We find the heights with integer arithmetic, so that they are exact for any
+    // number of entries.
+    let n = entries + 1;
+
+    // The smallest height at which the tree can hold the entries.
+    let mut h_min = 0;
+    let mut max_n = 2 * B;
+    while max_n < n {
+        h_min += 1;
+        max_n = max_n.saturating_mul(2 * B);
+    }
+
+    // The largest height at which the tree has enough entries to fill its nodes.
+    let mut h_max = 0;
+    let mut min_n = 2 * B;
+    while min_n <= n {
+        h_max += 1;
+        min_n = match min_n.checked_mul(B) {
+            Some(min_n) => min_n,
+            None => break,
+        };
+    }
+
+    (h_min, h_max)
+}
+
+/// Returns the lower and upper bounds on the memory allocated for the nodes of a
+/// `BTreeMap<K, V>` containing `entries` entries.
+pub(crate) fn btree_dynamic_usage_bounds<K, V>(entries: usize) -> (usize, usize) {
+    let (leaf_size, internal_size) = node_sizes::<K, V>();
+
+    // An empty map might still have an empty root node allocated, if it was emptied
+    // by removing its entries.
+    if entries == 0 {
+        return (0, leaf_size);
+    }
+
+    let (h_min, h_max) = btree_height_bounds(entries);
+
+    // The tree is as small as possible when every node is full. However, a tree of
+    // height h must also have at least as many nodes as the sparsest possible tree of
+    // that height: a root node with two children, and every other internal node having
+    // B children.
+    let lower = {
+        let min_nodes_for_height = |h: usize| 1 + 2 * (B.pow(h as u32) - 1) / (B - 1);
+        let nodes = usize::max(
+            (entries + CAPACITY - 1) / CAPACITY,
+            min_nodes_for_height(h_min),
+        );
+        let internal_nodes = match h_min {
+            0 => 0,
+            h => min_nodes_for_height(h - 1),
+        };
+        nodes * leaf_size + internal_nodes * (internal_size - leaf_size)
+    };
+
+    // The tree is as large as possible when every non-root node holds the minimum
+    // number of entries, and every internal node has the minimum number of children.
+    let upper = if h_max == 0 {
+        leaf_size
+    } else {
+        let nodes = 1 + (entries - 1) / (B - 1);
+        let internal_nodes = 1 + nodes.saturating_sub(3) / B;
+        nodes * leaf_size + internal_nodes * (internal_size - leaf_size)
+    };
+
+    (lower, upper)
+}
+
+impl<K: DynamicUsage, V: DynamicUsage> DynamicUsage for BTreeMap<K, V> {
+    fn dynamic_usage(&self) -> usize {
+        let (lower, upper) = btree_dynamic_usage_bounds::<K, V>(self.len());
+        // Use the midpoint of the bounds as our estimate.
+        lower
+            + (upper - lower) / 2
+            + self
+                .iter()
+                .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+                .sum::<usize>()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = btree_dynamic_usage_bounds::<K, V>(self.len());
+        let (inner_lower, inner_upper) = self
+            .iter()
+            .map(|(k, v)| {
+                let (k_lower, k_upper) = k.dynamic_usage_bounds();
+                let (v_lower, v_upper) = v.dynamic_usage_bounds();
+                (k_lower + v_lower, k_upper.zip(v_upper).map(|(k, v)| k + v))
+            })
+            .fold((0, Some(0)), |(acc_lower, acc_upper), (lower, upper)| {
+                (acc_lower + lower, acc_upper.zip(upper).map(|(a, b)| a + b))
+            });
+        (lower + inner_lower, inner_upper.map(|u| upper + u))
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        let (lower, upper) = btree_dynamic_usage_bounds::<K, V>(self.len());
+        self.iter()
+            .try_fold(lower + (upper - lower) / 2, |acc, (k, v)| {
+                Some(acc + k.try_dynamic_usage()? + v.try_dynamic_usage()?)
+            })
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.iter()
+            .any(|(k, v)| k.may_overcount() || v.may_overcount())
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        let (lower, upper) = btree_dynamic_usage_bounds::<K, V>(self.len());
+        lower
+            + (upper - lower) / 2
+            + self
+                .iter()
+                .map(|(k, v)| k.dedup_dynamic_usage(visited) + v.dedup_dynamic_usage(visited))
+                .sum::<usize>()
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        // Nodes are allocated as entries are inserted, so the estimated size of the nodes
+        // is all counted.
+        let (lower, upper) = btree_dynamic_usage_bounds::<K, V>(self.len());
+        lower
+            + (upper - lower) / 2
+            + self
+                .iter()
+                .map(|(k, v)| k.dynamic_usage_used() + v.dynamic_usage_used())
+                .sum::<usize>()
+    }
+}
+
+impl<T: DynamicUsage> DynamicUsage for BTreeSet<T> {
+    fn dynamic_usage(&self) -> usize {
+        // BTreeSet<T> is just BTreeMap<T, ()>
+        let (lower, upper) = btree_dynamic_usage_bounds::<T, ()>(self.len());
+        lower + (upper - lower) / 2 + self.iter().map(DynamicUsage::dynamic_usage).sum::<usize>()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = btree_dynamic_usage_bounds::<T, ()>(self.len());
+        let (inner_lower, inner_upper) = self.iter().map(DynamicUsage::dynamic_usage_bounds).fold(
+            (0, Some(0)),
+            |(acc_lower, acc_upper), (lower, upper)| {
+                (acc_lower + lower, acc_upper.zip(upper).map(|(a, b)| a + b))
+            },
+        );
+        (lower + inner_lower, inner_upper.map(|u| upper + u))
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        let (lower, upper) = btree_dynamic_usage_bounds::<T, ()>(self.len());
+        self.iter().try_fold(lower + (upper - lower) / 2, |acc, k| {
+            Some(acc + k.try_dynamic_usage()?)
+        })
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.iter().any(DynamicUsage::may_overcount)
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        let (lower, upper) = btree_dynamic_usage_bounds::<T, ()>(self.len());
+        lower
+            + (upper - lower) / 2
+            + self
+                .iter()
+                .map(|k| k.dedup_dynamic_usage(visited))
+                .sum::<usize>()
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        let (lower, upper) = btree_dynamic_usage_bounds::<T, ()>(self.len());
+        lower
+            + (upper - lower) / 2
+            + self
+                .iter()
+                .map(DynamicUsage::dynamic_usage_used)
+                .sum::<usize>()
+    }
+}
+
+impl<K: DynamicUsage, V: DynamicUsage> MapUsage for BTreeMap<K, V> {
+    fn value_size_histogram(&self) -> BTreeMap<usize, usize> {
+        size_histogram(self.values().map(DynamicUsage::dynamic_usage))
+    }
+
+    fn entry_sizes(&self) -> Vec<usize> {
+        self.iter()
+            .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+            .collect()
+    }
+
+    fn projected_usage(&self, additional: usize) -> usize {
+        // BTreeMap allocates nodes as needed, so we estimate its node usage at the new
+        // length in the same way as `DynamicUsage::dynamic_usage`.
+        let (lower, upper) = btree_dynamic_usage_bounds::<K, V>(self.len() + additional);
+        lower
+            + (upper - lower) / 2
+            + self
+                .iter()
+                .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+                .sum::<usize>()
+    }
+
+    #[cfg(feature = "std")]
+    fn write_usage_report<W: std::io::Write>(&self, writer: W) -> std::io::Result<()> {
+        let (lower, upper) = btree_dynamic_usage_bounds::<K, V>(self.len());
+        write_map_usage_report(
+            writer,
+            lower + (upper - lower) / 2,
+            self.iter()
+                .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage()),
+        )
+    }
+}
+
+impl<K: DynamicUsage, V: DynamicUsage> ContainerUsage for BTreeMap<K, V> {
+    fn minimal_usage(&self) -> usize {
+        // BTreeMap allocates and frees its nodes as needed, and has no spare capacity
+        // to shrink.
+        self.dynamic_usage()
+    }
+
+    fn utilization(&self) -> f64 {
+        // BTreeMap has no capacity beyond the entries it contains.
+        1.0
+    }
+}
+
+impl<K, V> BTreeUsage for BTreeMap<K, V> {
+    fn estimated_height(&self) -> (u32, u32) {
+        let (h_min, h_max) = btree_height_bounds(self.len());
+        (h_min as u32, h_max as u32)
+    }
+}
+
+impl<T> BTreeUsage for BTreeSet<T> {
+    fn estimated_height(&self) -> (u32, u32) {
+        let (h_min, h_max) = btree_height_bounds(self.len());
+        (h_min as u32, h_max as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::*;
+
+    #[test]
+    fn height_bounds() {
+        assert_eq!(btree_height_bounds(0), (0, 0));
+
+        let pow = |base: usize, exp: usize| (0..exp).fold(1usize, |acc, _| acc * base);
+        for entries in (1..100_000).chain((100_000..10_000_000).step_by(9973)) {
+            let n = entries + 1;
+            let (h_min, h_max) = btree_height_bounds(entries);
+            assert!(h_min <= h_max);
+
+            // h_min is the smallest height with (2 * B)^(h + 1) >= n.
+            assert!(pow(2 * B, h_min + 1) >= n);
+            assert!(h_min == 0 || pow(2 * B, h_min) < n);
+
+            // h_max is the largest height with 2 * B^h <= n.
+            assert!(h_max == 0 || 2 * pow(B, h_max) <= n);
+            assert!(2 * pow(B, h_max + 1) > n);
+        }
+
+        // The heights don't overflow for the largest possible maps.
+        let (h_min, h_max) = btree_height_bounds(usize::MAX - 1);
+        assert!(h_min <= h_max);
+    }
+
+    #[test]
+    fn btreemap() {
+        let leaf_size = mem::size_of::<LeafNode<u64, u64>>();
+        let internal_size = mem::size_of::<InternalNode<u64, u64>>();
+
+        let mut a: BTreeMap<u64, u64> = BTreeMap::new();
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(leaf_size)));
+
+        // A tree with fewer than CAPACITY entries must be a single leaf node.
+        a.extend((0..CAPACITY as u64 - 1).map(|i| (i, i)));
+        assert_eq!(a.dynamic_usage(), leaf_size);
+        assert_eq!(a.dynamic_usage_bounds(), (leaf_size, Some(leaf_size)));
+
+        // A tree with more than CAPACITY entries must have at least two levels.
+        a.extend((CAPACITY as u64 - 1..=CAPACITY as u64).map(|i| (i, i)));
+        let (lower, upper) = a.dynamic_usage_bounds();
+        assert_eq!(lower, 2 * leaf_size + internal_size);
+        assert!(upper.unwrap() >= lower);
+    }
+
+    #[test]
+    fn btreemap_estimated_height() {
+        let mut a: BTreeMap<u64, ()> = BTreeMap::new();
+        assert_eq!(a.estimated_height(), (0, 0));
+
+        a.extend((0..10).map(|i| (i, ())));
+        assert_eq!(a.estimated_height(), (0, 0));
+
+        // 11 entries fit in a single leaf, or a root with two children.
+        a.insert(10, ());
+        assert_eq!(a.estimated_height(), (0, 1));
+
+        a.insert(11, ());
+        assert_eq!(a.estimated_height(), (1, 1));
+
+        a.extend((12..1000).map(|i| (i, ())));
+        assert_eq!(a.estimated_height(), (2, 3));
+    }
+
+    #[test]
+    fn btreemap_nested() {
+        let a: BTreeMap<u8, Vec<u8>> = (0..5).map(|i| (i, Vec::with_capacity(10))).collect();
+        let leaf_size = mem::size_of::<LeafNode<u8, Vec<u8>>>();
+        assert_eq!(a.dynamic_usage(), leaf_size + 50);
+        assert_eq!(
+            a.dynamic_usage_bounds(),
+            (leaf_size + 50, Some(leaf_size + 50))
+        );
+    }
+
+    #[test]
+    fn btreemap_duration_keys() {
+        use core::time::Duration;
+
+        struct Event(Vec<u8>);
+        impl DynamicUsage for Event {
+            fn dynamic_usage(&self) -> usize {
+                self.0.dynamic_usage()
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+                self.0.dynamic_usage_bounds()
+            }
+        }
+
+        let timeline: BTreeMap<Duration, Vec<Event>> = (0..8)
+            .map(|i| {
+                let mut events = Vec::with_capacity(i + 1);
+                events.push(Event(Vec::with_capacity(16)));
+                (Duration::from_millis(i as u64 * 250), events)
+            })
+            .collect();
+
+        // Every node has room for CAPACITY 16-byte `Duration` keys.
+        let leaf_size = mem::size_of::<LeafNode<Duration, Vec<Event>>>();
+        assert!(leaf_size >= CAPACITY * mem::size_of::<(Duration, Vec<Event>)>());
+
+        let events: usize = (1..=8).map(|cap| cap * mem::size_of::<Event>() + 16).sum();
+        assert_eq!(timeline.dynamic_usage(), leaf_size + events);
+        assert_eq!(
+            timeline.dynamic_usage_bounds(),
+            (leaf_size + events, Some(leaf_size + events))
+        );
+    }
+
+    #[test]
+    fn btreemap_value_size_histogram() {
+        let a: BTreeMap<usize, String> = (0..100).map(|i| (i, String::with_capacity(i))).collect();
+        assert_eq!(
+            a.value_size_histogram().into_iter().collect::<Vec<_>>(),
+            [
+                (0, 1),
+                (1, 1),
+                (2, 2),
+                (4, 4),
+                (8, 8),
+                (16, 16),
+                (32, 32),
+                (64, 36)
+            ],
+        );
+    }
+
+    #[test]
+    fn btreemap_projected_usage() {
+        let mut a: BTreeMap<u64, Vec<u8>> = BTreeMap::new();
+        assert_eq!(a.projected_usage(0), a.dynamic_usage());
+
+        a.extend((0..5).map(|i| (i, Vec::with_capacity(10))));
+        assert_eq!(a.projected_usage(0), a.dynamic_usage());
+
+        let projected = a.projected_usage(1000);
+        a.extend((5..1005).map(|i| (i, Vec::new())));
+        assert_eq!(a.dynamic_usage(), projected);
+    }
+
+    #[test]
+    fn btreemap_shrink_savings() {
+        let a: BTreeMap<u64, Vec<u8>> = (0..100).map(|i| (i, Vec::with_capacity(10))).collect();
+        assert_eq!(a.minimal_usage(), a.dynamic_usage());
+        assert_eq!(a.shrink_savings(), 0);
+        assert_eq!(a.utilization(), 1.0);
+    }
+
+    #[test]
+    fn btreeset() {
+        let a: BTreeSet<u32> = (0..5).collect();
+        let leaf_size = mem::size_of::<LeafNode<u32, ()>>();
+        assert_eq!(a.dynamic_usage(), leaf_size);
+        assert_eq!(a.dynamic_usage_bounds(), (leaf_size, Some(leaf_size)));
+    }
+}
@@ -1,14 +1,24 @@
 //! `DynamicUsage` impls for `HashMap` and `HashSet`.
 //!
-//! Rust's `HashMap` and `HashSet` are backed by the the `hashbrown` crate.
+//! Rust's `HashMap` and `HashSet` are backed by the the `hashbrown` crate. The same
+//! impls are provided for `hashbrown`'s own types (behind the `hashbrown` feature flag),
+//! which are available in `no_std` environments, and for `indexmap`'s types (behind the
+//! `indexmap` feature flag), which store their indices in a `hashbrown` table.
 
 use alloc::alloc::Layout;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 use core::mem;
 
+#[cfg(feature = "dashmap")]
+use core::hash::{BuildHasher, Hash};
 #[cfg(feature = "std")]
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::RandomState;
 
-use crate::DynamicUsage;
+use crate::{size_histogram, utilization, ContainerUsage, DynamicUsage, MapUsage, Visited};
+
+#[cfg(feature = "std")]
+use crate::write_map_usage_report;
 
 // The widths are sourced from here:
 //   https://github.com/rust-lang/hashbrown/blob/dbd6dbe30a4076c0ea65ca5bd57036c27f3cc7c9/src/raw/mod.rs#L15-L36
@@ -42,16 +52,25 @@ fn dynamic_usage_for_capacity<K, V>(cap: usize) -> usize {
     // The bucket calculation is sourced from here:
     //   https://github.com/rust-lang/hashbrown/blob/dbd6dbe30a4076c0ea65ca5bd57036c27f3cc7c9/src/raw/mod.rs#L187-L216
     //
-    // hashbrown's RawTable::buckets is not accessible via the std HashMap, and
-    // HashMap::capacity is a lower bound. However, hashbrown has an invariant that
+    // hashbrown's RawTable::buckets is not accessible via the std HashMap (and
+    // hashbrown's own HashMap only exposes its RawTable through a mutable reference),
+    // and HashMap::capacity is a lower bound. However, hashbrown has an invariant that
     // the number of buckets is a power of two, so usually we'll calculate the correct
     // memory usage, and occasionally we'll undercount by around a factor of two.
     let buckets = {
-        if cap < 8 {
-            return if cap < 4 { 4 } else { 8 };
+        if cap == 0 {
+            // Empty tables don't allocate.
+            return 0;
+        } else if cap < 8 {
+            if cap < 4 {
+                4
+            } else {
+                8
+            }
+        } else {
+            let adjusted_cap = (cap * 8) / 7;
+            adjusted_cap.next_power_of_two()
         }
-        let adjusted_cap = (cap * 8) / 7;
-        adjusted_cap.next_power_of_two()
     };
 
     // The memory usage calculation is sourced from here:
@@ -65,9 +84,257 @@ fn dynamic_usage_for_capacity<K, V>(cap: usize) -> usize {
     ctrl_offset + buckets + WIDTH
 }
 
-impl<K: DynamicUsage, V: DynamicUsage> DynamicUsage for HashMap<K, V> {
+/// Returns the largest number of entries that a `HashMap<K, V>` can hold without its
+/// table allocation exceeding `budget` bytes.
+pub(crate) fn entries_within_budget<K, V>(budget: usize) -> usize {
+    // Tables have a power-of-two number of buckets, and the capacity of a table is
+    // fixed by its number of buckets. We find the largest table within the budget.
+    let mut entries = 0;
+    let mut buckets: usize = 4;
+    loop {
+        // Every bucket requires at least one control byte. We also stop before the
+        // capacity calculations could overflow.
+        match mem::size_of::<(K, V)>().checked_mul(buckets) {
+            Some(size) if size <= budget && buckets <= budget && buckets <= usize::MAX / 8 => {}
+            _ => break,
+        }
+
+        let cap = if buckets < 8 {
+            buckets - 1
+        } else {
+            buckets / 8 * 7
+        };
+        if dynamic_usage_for_capacity::<K, V>(cap) > budget {
+            break;
+        }
+        entries = cap;
+
+        buckets = match buckets.checked_mul(2) {
+            Some(buckets) => buckets,
+            None => break,
+        };
+    }
+    entries
+}
+
+#[cfg(feature = "std")]
+impl DynamicUsage for RandomState {
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        0
+    }
+
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
+
+/// Implements `DynamicUsage` and `MapUsage` for the `HashMap` and `HashSet` types in the
+/// given module, which must be backed by `hashbrown`.
+macro_rules! impl_hash_collections_dynamic_usage {
+    ($($collections:ident)::+) => {
+    /// The table's number of buckets isn't exposed, so it is inferred from the map's
+    /// capacity. This gives a lower bound, but not an upper bound: removing entries
+    /// leaves tombstones in the table, which reduce the map's reported capacity (down to
+    /// its length) without freeing any buckets. The upper bound is therefore `None`, even
+    /// for a map that isn't going to grow.
+    impl<K: DynamicUsage, V: DynamicUsage, S: DynamicUsage> DynamicUsage for $($collections)::+::HashMap<K, V, S> {
+        fn dynamic_usage(&self) -> usize {
+            dynamic_usage_for_capacity::<K, V>(self.capacity())
+                + self.hasher().dynamic_usage()
+                + self
+                    .iter()
+                    .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+                    .sum::<usize>()
+        }
+
+        fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+            (
+                dynamic_usage_for_capacity::<K, V>(self.capacity())
+                    + self.hasher().dynamic_usage_bounds().0
+                    + self
+                        .iter()
+                        .map(|(k, v)| k.dynamic_usage_bounds().0 + v.dynamic_usage_bounds().0)
+                        .sum::<usize>(),
+                None,
+            )
+        }
+
+        fn try_dynamic_usage(&self) -> Option<usize> {
+            self.iter().try_fold(
+                dynamic_usage_for_capacity::<K, V>(self.capacity())
+                    + self.hasher().try_dynamic_usage()?,
+                |acc, (k, v)| Some(acc + k.try_dynamic_usage()? + v.try_dynamic_usage()?),
+            )
+        }
+
+        fn may_overcount(&self) -> bool {
+            self.iter()
+                .any(|(k, v)| k.may_overcount() || v.may_overcount())
+        }
+
+        fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+            dynamic_usage_for_capacity::<K, V>(self.capacity())
+                + self.hasher().dedup_dynamic_usage(visited)
+                + self
+                    .iter()
+                    .map(|(k, v)| k.dedup_dynamic_usage(visited) + v.dedup_dynamic_usage(visited))
+                    .sum::<usize>()
+        }
+
+        fn dynamic_usage_used(&self) -> usize {
+            // Only the buckets holding entries are counted.
+            self.len() * mem::size_of::<(K, V)>()
+                + self.hasher().dynamic_usage_used()
+                + self
+                    .iter()
+                    .map(|(k, v)| k.dynamic_usage_used() + v.dynamic_usage_used())
+                    .sum::<usize>()
+        }
+    }
+
+    impl<T: DynamicUsage, S: DynamicUsage> DynamicUsage for $($collections)::+::HashSet<T, S> {
+        fn dynamic_usage(&self) -> usize {
+            // HashSet<T, S> is just HashMap<T, (), S>
+            dynamic_usage_for_capacity::<T, ()>(self.capacity())
+                + self.hasher().dynamic_usage()
+                + self.iter().map(DynamicUsage::dynamic_usage).sum::<usize>()
+        }
+
+        fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+            (
+                dynamic_usage_for_capacity::<T, ()>(self.capacity())
+                    + self.hasher().dynamic_usage_bounds().0
+                    + self
+                        .iter()
+                        .map(|k| k.dynamic_usage_bounds().0)
+                        .sum::<usize>(),
+                None,
+            )
+        }
+
+        fn try_dynamic_usage(&self) -> Option<usize> {
+            self.iter().try_fold(
+                dynamic_usage_for_capacity::<T, ()>(self.capacity())
+                    + self.hasher().try_dynamic_usage()?,
+                |acc, k| Some(acc + k.try_dynamic_usage()?),
+            )
+        }
+
+        fn may_overcount(&self) -> bool {
+            self.iter().any(DynamicUsage::may_overcount)
+        }
+
+        fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+            dynamic_usage_for_capacity::<T, ()>(self.capacity())
+                + self.hasher().dedup_dynamic_usage(visited)
+                + self
+                    .iter()
+                    .map(|k| k.dedup_dynamic_usage(visited))
+                    .sum::<usize>()
+        }
+
+        fn dynamic_usage_used(&self) -> usize {
+            // Only the buckets holding entries are counted.
+            self.len() * mem::size_of::<T>()
+                + self.hasher().dynamic_usage_used()
+                + self
+                    .iter()
+                    .map(DynamicUsage::dynamic_usage_used)
+                    .sum::<usize>()
+        }
+    }
+
+    impl<K: DynamicUsage, V: DynamicUsage, S: DynamicUsage> MapUsage for $($collections)::+::HashMap<K, V, S> {
+        fn value_size_histogram(&self) -> BTreeMap<usize, usize> {
+            size_histogram(self.values().map(DynamicUsage::dynamic_usage))
+        }
+
+        fn entry_sizes(&self) -> Vec<usize> {
+            self.iter()
+                .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+                .collect()
+        }
+
+        fn projected_usage(&self, additional: usize) -> usize {
+            // The growth calculation is sourced from here:
+            //   https://github.com/rust-lang/hashbrown/blob/dbd6dbe30a4076c0ea65ca5bd57036c27f3cc7c9/src/raw/mod.rs#L1063-L1086
+            //
+            // If the new entries don't fit, the table is resized to hold at least one more
+            // entry than its current capacity.
+            let needed = self.len() + additional;
+            let cap = if needed <= self.capacity() {
+                self.capacity()
+            } else {
+                usize::max(needed, self.capacity() + 1)
+            };
+
+            dynamic_usage_for_capacity::<K, V>(cap)
+                + self.hasher().dynamic_usage()
+                + self
+                    .iter()
+                    .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+                    .sum::<usize>()
+        }
+
+        #[cfg(feature = "std")]
+        fn write_usage_report<W: std::io::Write>(&self, writer: W) -> std::io::Result<()> {
+            write_map_usage_report(
+                writer,
+                dynamic_usage_for_capacity::<K, V>(self.capacity()) + self.hasher().dynamic_usage(),
+                self.iter().map(|(k, v)| k.dynamic_usage() + v.dynamic_usage()),
+            )
+        }
+    }
+
+    impl<K: DynamicUsage, V: DynamicUsage, S: DynamicUsage> ContainerUsage
+        for $($collections)::+::HashMap<K, V, S>
+    {
+        fn minimal_usage(&self) -> usize {
+            // Shrinking the table never grows it, even if our estimate of its current
+            // size is too small.
+            usize::min(
+                dynamic_usage_for_capacity::<K, V>(self.len()),
+                dynamic_usage_for_capacity::<K, V>(self.capacity()),
+            ) + self.hasher().dynamic_usage()
+                + self
+                    .iter()
+                    .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+                    .sum::<usize>()
+        }
+
+        fn utilization(&self) -> f64 {
+            utilization(self.len(), self.capacity())
+        }
+    }
+    };
+}
+
+#[cfg(feature = "std")]
+impl_hash_collections_dynamic_usage!(std::collections);
+
+#[cfg(feature = "hashbrown")]
+impl_hash_collections_dynamic_usage!(hashbrown);
+
+/// Returns the dynamic memory usage of the storage of an `indexmap::IndexMap<K, V>` that
+/// holds `cap` entries.
+///
+/// An `IndexMap` stores its entries in a `Vec`, alongside a `hashbrown` table of indices
+/// into that `Vec`. Each entry is stored with its hash; the layout of `indexmap`'s entry
+/// type is private, so we assume it is the same as that of `(usize, K, V)`.
+#[cfg(any(feature = "indexmap", feature = "serde_json_preserve_order"))]
+pub(crate) fn indexmap_usage_for_capacity<K, V>(cap: usize) -> usize {
+    dynamic_usage_for_capacity::<usize, ()>(cap) + cap * mem::size_of::<(usize, K, V)>()
+}
+
+#[cfg(feature = "indexmap")]
+impl<K: DynamicUsage, V: DynamicUsage, S: DynamicUsage> DynamicUsage
+    for indexmap::IndexMap<K, V, S>
+{
     fn dynamic_usage(&self) -> usize {
-        dynamic_usage_for_capacity::<K, V>(self.capacity())
+        indexmap_usage_for_capacity::<K, V>(self.capacity())
+            + self.hasher().dynamic_usage()
             + self
                 .iter()
                 .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
@@ -75,8 +342,10 @@ impl<K: DynamicUsage, V: DynamicUsage> DynamicUsage for HashMap<K, V> {
     }
 
     fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        // The lower bound only counts storage for the occupied entries.
         (
-            dynamic_usage_for_capacity::<K, V>(self.capacity())
+            indexmap_usage_for_capacity::<K, V>(self.len())
+                + self.hasher().dynamic_usage_bounds().0
                 + self
                     .iter()
                     .map(|(k, v)| k.dynamic_usage_bounds().0 + v.dynamic_usage_bounds().0)
@@ -84,18 +353,54 @@ impl<K: DynamicUsage, V: DynamicUsage> DynamicUsage for HashMap<K, V> {
             None,
         )
     }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.iter().try_fold(
+            indexmap_usage_for_capacity::<K, V>(self.capacity())
+                + self.hasher().try_dynamic_usage()?,
+            |acc, (k, v)| Some(acc + k.try_dynamic_usage()? + v.try_dynamic_usage()?),
+        )
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.iter()
+            .any(|(k, v)| k.may_overcount() || v.may_overcount())
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        indexmap_usage_for_capacity::<K, V>(self.capacity())
+            + self.hasher().dedup_dynamic_usage(visited)
+            + self
+                .iter()
+                .map(|(k, v)| k.dedup_dynamic_usage(visited) + v.dedup_dynamic_usage(visited))
+                .sum::<usize>()
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        // Only the storage for the occupied entries is counted.
+        indexmap_usage_for_capacity::<K, V>(self.len())
+            + self.hasher().dynamic_usage_used()
+            + self
+                .iter()
+                .map(|(k, v)| k.dynamic_usage_used() + v.dynamic_usage_used())
+                .sum::<usize>()
+    }
 }
 
-impl<T: DynamicUsage> DynamicUsage for HashSet<T> {
+#[cfg(feature = "indexmap")]
+impl<T: DynamicUsage, S: DynamicUsage> DynamicUsage for indexmap::IndexSet<T, S> {
     fn dynamic_usage(&self) -> usize {
-        // HashSet<T> is just HashMap<T, ()>
-        dynamic_usage_for_capacity::<T, ()>(self.capacity())
+        // IndexSet<T, S> is just IndexMap<T, (), S>
+        indexmap_usage_for_capacity::<T, ()>(self.capacity())
+            + self.hasher().dynamic_usage()
             + self.iter().map(DynamicUsage::dynamic_usage).sum::<usize>()
     }
 
     fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        // The lower bound only counts storage for the occupied entries.
         (
-            dynamic_usage_for_capacity::<T, ()>(self.capacity())
+            indexmap_usage_for_capacity::<T, ()>(self.len())
+                + self.hasher().dynamic_usage_bounds().0
                 + self
                     .iter()
                     .map(|k| k.dynamic_usage_bounds().0)
@@ -103,10 +408,169 @@ impl<T: DynamicUsage> DynamicUsage for HashSet<T> {
             None,
         )
     }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.iter().try_fold(
+            indexmap_usage_for_capacity::<T, ()>(self.capacity())
+                + self.hasher().try_dynamic_usage()?,
+            |acc, k| Some(acc + k.try_dynamic_usage()?),
+        )
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.iter().any(DynamicUsage::may_overcount)
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        indexmap_usage_for_capacity::<T, ()>(self.capacity())
+            + self.hasher().dedup_dynamic_usage(visited)
+            + self
+                .iter()
+                .map(|k| k.dedup_dynamic_usage(visited))
+                .sum::<usize>()
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        // Only the storage for the occupied entries is counted.
+        indexmap_usage_for_capacity::<T, ()>(self.len())
+            + self.hasher().dynamic_usage_used()
+            + self
+                .iter()
+                .map(DynamicUsage::dynamic_usage_used)
+                .sum::<usize>()
+    }
 }
 
-#[cfg(test)]
+/// A `DashMap` is split into shards, each of which is a hash table behind its own lock.
+/// Measuring a shard requires acquiring its lock for reading. These impls never block: if
+/// a shard is locked for writing (including by the calling thread, for example through a
+/// `RefMut`), its table and entries are not measured. `dynamic_usage` counts nothing for
+/// it, `dynamic_usage_bounds` has a lower bound that excludes it, and
+/// `try_dynamic_usage` returns `None`.
+///
+/// As with `HashMap`, the upper bound is always `None`.
+#[cfg(feature = "dashmap")]
+impl<K, V, S> DynamicUsage for dashmap::DashMap<K, V, S>
+where
+    K: DynamicUsage + Eq + Hash,
+    V: DynamicUsage,
+    S: DynamicUsage + BuildHasher + Clone,
+{
+    fn dynamic_usage(&self) -> usize {
+        mem::size_of_val(self.shards())
+            + self.hasher().dynamic_usage()
+            + self
+                .shards()
+                .iter()
+                .filter_map(|shard| shard.try_read())
+                .map(|shard| {
+                    dynamic_usage_for_capacity::<K, V>(shard.capacity())
+                        + shard.hasher().dynamic_usage()
+                        + shard
+                            .iter()
+                            .map(|(k, v)| k.dynamic_usage() + v.get().dynamic_usage())
+                            .sum::<usize>()
+                })
+                .sum::<usize>()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (
+            mem::size_of_val(self.shards())
+                + self.hasher().dynamic_usage_bounds().0
+                + self
+                    .shards()
+                    .iter()
+                    .filter_map(|shard| shard.try_read())
+                    .map(|shard| {
+                        dynamic_usage_for_capacity::<K, V>(shard.capacity())
+                            + shard.hasher().dynamic_usage_bounds().0
+                            + shard
+                                .iter()
+                                .map(|(k, v)| {
+                                    k.dynamic_usage_bounds().0 + v.get().dynamic_usage_bounds().0
+                                })
+                                .sum::<usize>()
+                    })
+                    .sum::<usize>(),
+            None,
+        )
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.shards().iter().try_fold(
+            mem::size_of_val(self.shards()) + self.hasher().try_dynamic_usage()?,
+            |acc, shard| {
+                let shard = shard.try_read()?;
+                shard.iter().try_fold(
+                    acc + dynamic_usage_for_capacity::<K, V>(shard.capacity())
+                        + shard.hasher().try_dynamic_usage()?,
+                    |acc, (k, v)| Some(acc + k.try_dynamic_usage()? + v.get().try_dynamic_usage()?),
+                )
+            },
+        )
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.shards()
+            .iter()
+            .filter_map(|shard| shard.try_read())
+            .any(|shard| {
+                shard
+                    .iter()
+                    .any(|(k, v)| k.may_overcount() || v.get().may_overcount())
+            })
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        mem::size_of_val(self.shards())
+            + self.hasher().dedup_dynamic_usage(visited)
+            + self
+                .shards()
+                .iter()
+                .filter_map(|shard| shard.try_read())
+                .map(|shard| {
+                    dynamic_usage_for_capacity::<K, V>(shard.capacity())
+                        + shard.hasher().dedup_dynamic_usage(visited)
+                        + shard
+                            .iter()
+                            .map(|(k, v)| {
+                                k.dedup_dynamic_usage(visited)
+                                    + v.get().dedup_dynamic_usage(visited)
+                            })
+                            .sum::<usize>()
+                })
+                .sum::<usize>()
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        // Only the buckets holding entries are counted.
+        mem::size_of_val(self.shards())
+            + self.hasher().dynamic_usage_used()
+            + self
+                .shards()
+                .iter()
+                .filter_map(|shard| shard.try_read())
+                .map(|shard| {
+                    shard.len() * mem::size_of::<(K, V)>()
+                        + shard.hasher().dynamic_usage_used()
+                        + shard
+                            .iter()
+                            .map(|(k, v)| k.dynamic_usage_used() + v.get().dynamic_usage_used())
+                            .sum::<usize>()
+                })
+                .sum::<usize>()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
+    use alloc::borrow::Cow;
+    use alloc::string::{String, ToString};
+    use core::hash::{BuildHasher, Hash};
+    use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
+    use std::sync::Mutex;
+
     use super::*;
 
     #[test]
@@ -121,6 +585,550 @@ mod tests {
         assert_eq!(h.dynamic_usage_bounds(), (lower, None));
     }
 
+    #[test]
+    fn hashmap_entry_sizes() {
+        let mut h: HashMap<String, Vec<u64>> = HashMap::new();
+        assert!(h.entry_sizes().is_empty());
+
+        h.insert(String::from("foo"), Vec::with_capacity(2));
+        h.insert(String::from("barr"), Vec::with_capacity(100));
+        h.insert(String::new(), Vec::new());
+
+        let mut sizes = h.entry_sizes();
+        sizes.sort_unstable();
+        assert_eq!(sizes, [0, 3 + 16, 4 + 800]);
+    }
+
+    #[test]
+    fn hashmap_write_usage_report() {
+        let mut h: HashMap<String, Vec<u64>> = HashMap::new();
+        let mut report = Vec::new();
+        h.write_usage_report(&mut report).unwrap();
+        assert_eq!(report, br#"{"storage":0,"entries":[],"total":0}"#);
+
+        h.insert(String::from("foo"), Vec::with_capacity(2));
+        h.insert(String::from("barr"), Vec::with_capacity(100));
+        h.insert(String::new(), Vec::new());
+
+        let mut report = Vec::new();
+        h.write_usage_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+
+        // The entries are written in iteration order.
+        let entries = h
+            .entry_sizes()
+            .iter()
+            .map(|size| size.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let storage = dynamic_usage_for_capacity::<String, Vec<u64>>(h.capacity());
+        assert_eq!(
+            report,
+            format!(
+                r#"{{"storage":{},"entries":[{}],"total":{}}}"#,
+                storage,
+                entries,
+                h.dynamic_usage(),
+            ),
+        );
+    }
+
+    #[test]
+    fn hashmap_cow_keys() {
+        let mut h: HashMap<Cow<'static, str>, u64> = HashMap::with_capacity(12);
+        let table = dynamic_usage_for_capacity::<Cow<'static, str>, u64>(h.capacity());
+
+        // Borrowed keys don't contribute to the map's usage.
+        h.insert(Cow::Borrowed("static-key"), 1);
+        h.insert(Cow::Borrowed("another-static-key"), 2);
+        assert_eq!(h.dynamic_usage(), table);
+
+        // Owned keys contribute their capacity.
+        h.insert(Cow::Owned(String::with_capacity(20)), 3);
+        h.insert(Cow::Owned(String::from("dynamic-key")), 4);
+        assert_eq!(h.dynamic_usage(), table + 20 + 11);
+        assert_eq!(h.dynamic_usage_bounds(), (table + 20 + 11, None));
+    }
+
+    #[test]
+    fn hashmap_array_keys() {
+        let mut h: HashMap<[u8; 32], Vec<u8>> = HashMap::with_capacity(12);
+        h.insert([1; 32], Vec::with_capacity(100));
+        h.insert([2; 32], Vec::with_capacity(200));
+
+        // - Capacity of 12 -> 16 buckets
+        // - Each bucket stores the 32-byte key inline
+        // - Overhead is 1 byte per bucket
+        // - Fixed overhead of WIDTH
+        // - Keys don't allocate, so only the values contribute
+        let bucket_size = mem::size_of::<([u8; 32], Vec<u8>)>();
+        assert!(bucket_size >= 32 + mem::size_of::<Vec<u8>>());
+        let table = 16 * (bucket_size + 1) + WIDTH;
+        assert_eq!(h.dynamic_usage(), table + 300);
+        assert_eq!(h.dynamic_usage_bounds(), (table + 300, None));
+    }
+
+    #[test]
+    fn hashmap_small() {
+        let mut h: HashMap<u16, u32> = HashMap::new();
+        assert_eq!(h.dynamic_usage(), 0);
+        assert_eq!(h.dynamic_usage_bounds(), (0, None));
+
+        // - Capacity of 1 -> at least 4 buckets
+        // - Overhead is 1 byte per bucket
+        // - Fixed overhead of WIDTH
+        h.insert(1, 2);
+        let buckets = (h.capacity() + 1).next_power_of_two().max(4);
+        let lower = buckets * (mem::size_of::<(u16, u32)>() + 1) + WIDTH;
+        assert_eq!(h.dynamic_usage(), lower);
+        assert_eq!(h.dynamic_usage_bounds(), (lower, None));
+    }
+
+    #[test]
+    fn hashmap_clear() {
+        let mut h: HashMap<u16, Vec<u8>> = HashMap::new();
+        h.reserve(1000);
+        let reserved = h.dynamic_usage();
+
+        h.extend((0..1000).map(|i| (i, Vec::with_capacity(10))));
+        assert_eq!(h.dynamic_usage(), reserved + 10_000);
+
+        // Clearing a map drops its entries, but keeps its allocated table.
+        h.clear();
+        assert_eq!(h.dynamic_usage(), reserved);
+
+        h.shrink_to_fit();
+        assert_eq!(h.dynamic_usage(), 0);
+    }
+
+    #[test]
+    fn hashmap_measure_while_iterating() {
+        let h: HashMap<u8, Vec<u8>> = (0..10).map(|i| (i, Vec::with_capacity(i.into()))).collect();
+        let expected = h.dynamic_usage();
+
+        // Measuring only requires a shared borrow, so it can be interleaved with iteration.
+        let mut entries = 0;
+        for (k, v) in h.iter() {
+            assert_eq!(h.dynamic_usage(), expected);
+            entries += k.dynamic_usage() + v.dynamic_usage();
+        }
+        assert_eq!(entries, 45);
+    }
+
+    #[test]
+    fn hashmap_option() {
+        let a: Option<HashMap<u64, Vec<u8>>> = None;
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        // An empty map reports the table it has reserved.
+        let b: Option<HashMap<u64, Vec<u8>>> = Some(HashMap::with_capacity(1000));
+        let reserved = dynamic_usage_for_capacity::<u64, Vec<u8>>(b.as_ref().unwrap().capacity());
+        assert!(reserved > 1000 * mem::size_of::<(u64, Vec<u8>)>());
+        assert_eq!(b.dynamic_usage(), reserved);
+        assert_eq!(b.dynamic_usage_bounds(), (reserved, None));
+    }
+
+    #[test]
+    fn hashmap_try_dynamic_usage() {
+        let mut h: HashMap<u8, Mutex<Vec<u8>>> = HashMap::with_capacity(12);
+        h.insert(1, Mutex::new(Vec::with_capacity(10)));
+        h.insert(2, Mutex::new(Vec::with_capacity(20)));
+
+        // - Capacity of 12 -> 16 buckets
+        // - Overhead is 1 byte per bucket
+        // - Fixed overhead of WIDTH
+        let expected = 16 * (mem::size_of::<(u8, Mutex<Vec<u8>>)>() + 1) + WIDTH + 30;
+        assert_eq!(h.try_dynamic_usage(), Some(expected));
+
+        // If any value is locked elsewhere, the map can't be measured without blocking.
+        let _guard = h[&2].lock().unwrap();
+        assert_eq!(h.try_dynamic_usage(), None);
+    }
+
+    #[test]
+    fn hashmap_stateful_hasher() {
+        struct SeededState {
+            seeds: Vec<u64>,
+        }
+
+        impl BuildHasher for SeededState {
+            type Hasher = DefaultHasher;
+
+            fn build_hasher(&self) -> DefaultHasher {
+                let mut hasher = DefaultHasher::new();
+                self.seeds.hash(&mut hasher);
+                hasher
+            }
+        }
+
+        impl DynamicUsage for SeededState {
+            fn dynamic_usage(&self) -> usize {
+                self.seeds.dynamic_usage()
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+                self.seeds.dynamic_usage_bounds()
+            }
+        }
+
+        let seeds = Vec::with_capacity(64);
+        let h: HashMap<u16, u32, _> = HashMap::with_capacity_and_hasher(12, SeededState { seeds });
+
+        // - Capacity of 12 -> 16 buckets
+        // - Overhead is 1 byte per bucket
+        // - Fixed overhead of WIDTH
+        // - Hasher state of 64 u64s
+        let lower = 16 * (mem::size_of::<(u16, u32)>() + 1) + WIDTH + 64 * 8;
+        assert_eq!(h.dynamic_usage(), lower);
+        assert_eq!(h.dynamic_usage_bounds(), (lower, None));
+    }
+
+    #[test]
+    fn hashmap_projected_usage() {
+        let mut h: HashMap<u64, Vec<u8>> = HashMap::with_capacity(3);
+        h.extend((0..3).map(|i| (i, Vec::with_capacity(10))));
+        assert_eq!(h.projected_usage(0), h.dynamic_usage());
+
+        // Entries that fit within the current capacity don't grow the table.
+        let projected = h.projected_usage(h.capacity() - h.len());
+        assert_eq!(projected, h.dynamic_usage());
+
+        // Inserting more entries grows the table.
+        let projected = h.projected_usage(10);
+        assert!(projected > h.dynamic_usage());
+        h.extend((3..13).map(|i| (i, Vec::new())));
+        assert_eq!(h.dynamic_usage(), projected);
+
+        // Growth one entry at a time reaches the same table size.
+        let mut h: HashMap<u64, u64> = HashMap::new();
+        let projected = h.projected_usage(50);
+        for i in 0..50 {
+            h.insert(i, i);
+        }
+        assert_eq!(h.dynamic_usage(), projected);
+    }
+
+    #[test]
+    fn hashmap_shrink_savings() {
+        let mut h: HashMap<u64, Vec<u8>> = HashMap::with_capacity(100);
+        assert_eq!(h.minimal_usage(), 0);
+        assert_eq!(h.shrink_savings(), h.dynamic_usage());
+
+        h.extend((0..3).map(|i| (i, Vec::with_capacity(10))));
+        let minimal = h.minimal_usage();
+        assert_eq!(minimal, dynamic_usage_for_capacity::<u64, Vec<u8>>(3) + 30);
+        assert_eq!(h.shrink_savings(), h.dynamic_usage() - minimal);
+
+        h.shrink_to_fit();
+        assert_eq!(h.dynamic_usage(), minimal);
+        assert_eq!(h.shrink_savings(), 0);
+    }
+
+    #[test]
+    fn hashmap_utilization() {
+        let mut h: HashMap<u64, u64> = HashMap::new();
+        assert_eq!(h.utilization(), 1.0);
+
+        h.reserve(14);
+        h.extend((0..7).map(|i| (i, i)));
+        assert_eq!(h.utilization(), 7.0 / h.capacity() as f64);
+        assert!(h.utilization() <= 0.5);
+    }
+
+    #[test]
+    fn hashmap_dynamic_usage_used() {
+        let mut h: HashMap<u64, String> = HashMap::with_capacity(100);
+        assert_eq!(h.dynamic_usage_used(), 0);
+
+        let mut value = String::with_capacity(10);
+        value.push_str("abc");
+        h.insert(1, value);
+        h.insert(2, String::new());
+        assert_eq!(
+            h.dynamic_usage_used(),
+            2 * mem::size_of::<(u64, String)>() + 3,
+        );
+        assert!(h.dynamic_usage_used() < h.dynamic_usage());
+
+        let s: HashSet<u32> = (0..5).collect();
+        assert_eq!(s.dynamic_usage_used(), 5 * 4);
+    }
+
+    #[test]
+    fn hashmap_weak_values() {
+        use std::sync::{Arc, Weak};
+
+        let values: Vec<Arc<Vec<u8>>> = (0..10).map(|_| Arc::new(vec![0; 100])).collect();
+        let h: HashMap<u32, Weak<Vec<u8>>> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as u32, Arc::downgrade(v)))
+            .collect();
+
+        // The weak pointers don't own the values, so only the table is counted.
+        let table = dynamic_usage_for_capacity::<u32, Weak<Vec<u8>>>(h.capacity());
+        assert_eq!(h.dynamic_usage(), table);
+        assert_eq!(h.dynamic_usage_bounds(), (table, None));
+
+        // Measuring doesn't upgrade the weak pointers.
+        assert!(values.iter().all(|v| Arc::strong_count(v) == 1));
+        drop(values);
+        assert_eq!(h.dynamic_usage(), table);
+    }
+
+    #[test]
+    fn hashmap_dedup_shared_subtrees() {
+        use alloc::rc::Rc;
+
+        // A cache of expressions, which all share the same large subexpression.
+        type Expr = (u32, Vec<Rc<Vec<u8>>>);
+        let shared = Rc::new(vec![0u8; 1000]);
+        let h: HashMap<u32, Rc<Expr>> = (0..10)
+            .map(|i| (i, Rc::new((i, vec![shared.clone(); 2]))))
+            .collect();
+
+        // Measuring each value independently counts the shared subexpression every time.
+        let naive: usize = h
+            .values()
+            .map(|v| v.dedup_dynamic_usage(&mut Visited::new()))
+            .sum();
+
+        // Measuring the map with a single visited set counts it once.
+        let table = dynamic_usage_for_capacity::<u32, Rc<Expr>>(h.capacity());
+        let expr_size = crate::rc_allocation_size(&(0u32, Vec::<Rc<Vec<u8>>>::new()))
+            + 2 * mem::size_of::<Rc<Vec<u8>>>();
+        let shared_size = crate::rc_allocation_size(&*shared) + 1000;
+        let mut visited = Visited::new();
+        let deduped = h.dedup_dynamic_usage(&mut visited);
+        assert_eq!(deduped, table + 10 * expr_size + shared_size);
+        assert_eq!(visited.len(), 11);
+        assert_eq!(naive, 10 * (expr_size + shared_size));
+        assert!(deduped - table < naive);
+    }
+
+    #[test]
+    fn hashmap_dedup_cycles() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        // Components that reference each other cyclically.
+        struct Component {
+            data: Vec<u8>,
+            refs: Vec<Rc<RefCell<Component>>>,
+        }
+
+        impl DynamicUsage for Component {
+            fn dynamic_usage(&self) -> usize {
+                self.data.dynamic_usage() + self.refs.dynamic_usage()
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+                let usage = self.dynamic_usage();
+                (usage, Some(usage))
+            }
+
+            fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+                self.data.dedup_dynamic_usage(visited) + self.refs.dedup_dynamic_usage(visited)
+            }
+        }
+
+        let a = Rc::new(RefCell::new(Component {
+            data: vec![0; 10],
+            refs: Vec::with_capacity(1),
+        }));
+        let b = Rc::new(RefCell::new(Component {
+            data: vec![0; 20],
+            refs: vec![a.clone()],
+        }));
+        a.borrow_mut().refs.push(b.clone());
+
+        let mut h: HashMap<u32, Rc<RefCell<Component>>> = HashMap::new();
+        h.insert(1, a.clone());
+        h.insert(2, b.clone());
+
+        // Each component's allocation is counted once, even though it is reachable both
+        // from the map and from the other component.
+        let table = dynamic_usage_for_capacity::<u32, Rc<RefCell<Component>>>(h.capacity());
+        let component_size =
+            crate::rc_allocation_size(&*a) + mem::size_of::<Rc<RefCell<Component>>>();
+        let mut visited = Visited::new();
+        assert_eq!(
+            h.dedup_dynamic_usage(&mut visited),
+            table + 2 * component_size + 10 + 20,
+        );
+        assert_eq!(visited.len(), 2);
+
+        // Break the cycle so the components are freed.
+        a.borrow_mut().refs.clear();
+    }
+
+    #[test]
+    fn hashmap_dedup_shared_hasher() {
+        use std::sync::Arc;
+
+        // A stateful hasher whose state is shared between maps.
+        #[derive(Clone)]
+        struct SharedHasher(Arc<Vec<u64>>);
+
+        impl BuildHasher for SharedHasher {
+            type Hasher = DefaultHasher;
+
+            fn build_hasher(&self) -> DefaultHasher {
+                let mut hasher = DefaultHasher::new();
+                self.0.hash(&mut hasher);
+                hasher
+            }
+        }
+
+        impl DynamicUsage for SharedHasher {
+            fn dynamic_usage(&self) -> usize {
+                self.0.dynamic_usage()
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+                self.0.dynamic_usage_bounds()
+            }
+
+            fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+                self.0.dedup_dynamic_usage(visited)
+            }
+        }
+
+        let hasher = SharedHasher(Arc::new(vec![7; 100]));
+        let shards: Vec<HashMap<u64, u64, SharedHasher>> = (0..2)
+            .map(|_| HashMap::with_capacity_and_hasher(8, hasher.clone()))
+            .collect();
+        drop(hasher);
+
+        let tables: usize = shards
+            .iter()
+            .map(|h| dynamic_usage_for_capacity::<u64, u64>(h.capacity()))
+            .sum();
+        let state_size = crate::rc_allocation_size(&Vec::<u64>::new()) + 800;
+
+        // The shared hasher state is counted once across both maps.
+        let mut visited = Visited::new();
+        let usage: usize = shards
+            .iter()
+            .map(|h| h.dedup_dynamic_usage(&mut visited))
+            .sum();
+        assert_eq!(usage, tables + state_size);
+
+        // Without deduplication, each map counts its share of the state.
+        assert_eq!(
+            shards.iter().map(|h| h.dynamic_usage()).sum::<usize>(),
+            tables + 2 * (state_size / 2),
+        );
+    }
+
+    #[test]
+    fn hashmap_duplicate_string_keys() {
+        let keys = ["alpha", "beta", "gamma"];
+        let a: HashMap<String, u64> = keys.iter().map(|k| (String::from(*k), 1)).collect();
+        let b: HashMap<String, u64> = keys.iter().map(|k| (String::from(*k), 2)).collect();
+
+        // Equal keys in separate maps are separate allocations, so both are counted.
+        let keys_usage: usize = a.keys().map(String::capacity).sum();
+        assert_eq!(
+            a.dynamic_usage(),
+            dynamic_usage_for_capacity::<String, u64>(a.capacity()) + keys_usage
+        );
+        assert_eq!(
+            crate::total_dynamic_usage([&a as &dyn DynamicUsage, &b]),
+            a.dynamic_usage() + b.dynamic_usage()
+        );
+        assert!(!a.may_overcount());
+    }
+
+    #[test]
+    fn hashmap_deeply_nested() {
+        struct Config {
+            value: Vec<u8>,
+            children: HashMap<u32, Config>,
+        }
+
+        impl DynamicUsage for Config {
+            fn dynamic_usage(&self) -> usize {
+                self.value.dynamic_usage() + self.children.dynamic_usage()
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+                let (value_lower, value_upper) = self.value.dynamic_usage_bounds();
+                let (children_lower, children_upper) = self.children.dynamic_usage_bounds();
+                (
+                    value_lower + children_lower,
+                    value_upper.zip(children_upper).map(|(a, b)| a + b),
+                )
+            }
+        }
+
+        const DEPTH: usize = 64;
+
+        let mut root = Config {
+            value: Vec::with_capacity(8),
+            children: HashMap::new(),
+        };
+        let mut expected = 8;
+
+        // Each level has the previous level as a child, along with a leaf sibling.
+        for depth in 1..DEPTH {
+            let mut children = HashMap::with_capacity(2);
+            children.insert(0, root);
+            children.insert(
+                1,
+                Config {
+                    value: Vec::with_capacity(depth),
+                    children: HashMap::new(),
+                },
+            );
+            expected += 8 + depth + dynamic_usage_for_capacity::<u32, Config>(children.capacity());
+            root = Config {
+                value: Vec::with_capacity(8),
+                children,
+            };
+        }
+
+        assert_eq!(root.dynamic_usage(), expected);
+        assert_eq!(root.dynamic_usage_bounds(), (expected, None));
+    }
+
+    #[test]
+    fn hashmap_entries_within_budget() {
+        assert_eq!(entries_within_budget::<u64, u64>(0), 0);
+        // Huge budgets don't overflow the table size calculation.
+        assert!(entries_within_budget::<u64, u64>(usize::MAX) > 0);
+        assert!(entries_within_budget::<u8, ()>(usize::MAX) > 0);
+
+        for budget in [1, 100, 1000, 4096, 10_000, 1 << 20] {
+            let entries = entries_within_budget::<u64, Vec<u8>>(budget);
+
+            let h: HashMap<u64, Vec<u8>> = HashMap::with_capacity(entries);
+            assert!(h.dynamic_usage() <= budget);
+            assert!(h.capacity() >= entries);
+
+            let h: HashMap<u64, Vec<u8>> = HashMap::with_capacity(entries + 1);
+            assert!(h.dynamic_usage() > budget);
+        }
+    }
+
+    #[test]
+    fn hashmap_value_size_histogram() {
+        let mut h: HashMap<u8, Vec<u8>> = HashMap::new();
+        assert!(h.value_size_histogram().is_empty());
+
+        h.insert(0, Vec::new());
+        h.insert(1, Vec::with_capacity(1));
+        h.insert(2, Vec::with_capacity(2));
+        h.insert(3, Vec::with_capacity(3));
+        h.insert(4, Vec::with_capacity(4));
+        h.insert(5, Vec::with_capacity(4000));
+        assert_eq!(
+            h.value_size_histogram().into_iter().collect::<Vec<_>>(),
+            [(0, 1), (1, 1), (2, 2), (4, 1), (2048, 1)],
+        );
+    }
+
     #[test]
     fn hashset() {
         let h: HashSet<u16> = HashSet::with_capacity(17);
@@ -132,4 +1140,31 @@ mod tests {
         assert_eq!(h.dynamic_usage(), lower);
         assert_eq!(h.dynamic_usage_bounds(), (lower, None));
     }
+
+    #[cfg(feature = "hashbrown")]
+    #[test]
+    fn hashbrown() {
+        use core::hash::BuildHasherDefault;
+
+        type Hasher = BuildHasherDefault<DefaultHasher>;
+
+        // The hashbrown version used by std may choose a different number of buckets for
+        // a given capacity, so we compare against the capacity of each map.
+        for capacity in [0, 1, 3, 4, 7, 8, 12, 17, 100] {
+            let mut a: hashbrown::HashMap<u16, String, Hasher> =
+                hashbrown::HashMap::with_capacity_and_hasher(capacity, Hasher::default());
+            let table = dynamic_usage_for_capacity::<u16, String>(a.capacity());
+            assert_eq!(a.dynamic_usage(), table);
+
+            a.insert(7, String::from("foobar"));
+            let table = dynamic_usage_for_capacity::<u16, String>(a.capacity());
+            assert_eq!(a.dynamic_usage(), table + 6);
+            assert_eq!(a.dynamic_usage_bounds(), (table + 6, None));
+
+            let b: hashbrown::HashSet<u16, Hasher> =
+                hashbrown::HashSet::with_capacity_and_hasher(capacity, Hasher::default());
+            let table = dynamic_usage_for_capacity::<u16, ()>(b.capacity());
+            assert_eq!(b.dynamic_usage(), table);
+        }
+    }
 }
@@ -0,0 +1,66 @@
+//! Sizes from the memory layouts of standard library collections.
+//!
+//! `memuse` estimates the dynamic memory usage of some collections by mirroring their
+//! private memory layouts. This module exposes the resulting sizes, so that they can be
+//! used to build custom estimates. They are derived from implementation details of the
+//! standard library, and may change between Rust versions.
+
+use core::mem;
+
+use crate::{btree, LinkedListNode};
+
+/// The maximum number of entries in a node of a `BTreeMap` or `BTreeSet`.
+pub const BTREE_NODE_CAPACITY: usize = btree::CAPACITY;
+
+/// Returns the sizes in bytes of the leaf and internal nodes of a `BTreeMap<K, V>`.
+///
+/// A `BTreeSet<T>` has the same nodes as a `BTreeMap<T, ()>`.
+///
+/// ```
+/// use memuse::layout::btree_node_sizes;
+///
+/// let (leaf, internal) = btree_node_sizes::<u64, u64>();
+/// assert!(leaf < internal);
+/// ```
+pub fn btree_node_sizes<K, V>() -> (usize, usize) {
+    btree::node_sizes::<K, V>()
+}
+
+/// The number of control bytes in each group of a `HashMap` or `HashSet`'s table.
+///
+/// A table with `n` buckets has `n + HASH_GROUP_WIDTH` control bytes.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub const HASH_GROUP_WIDTH: usize = crate::hash::WIDTH;
+
+/// Returns the size in bytes of each node of a `LinkedList<T>`.
+pub fn linked_list_node_size<T>() -> usize {
+    mem::size_of::<LinkedListNode<T>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn btree() {
+        // Leaf nodes hold a parent pointer, two u16s, and the keys and values.
+        let (leaf, internal) = btree_node_sizes::<u64, u64>();
+        assert_eq!(
+            leaf,
+            mem::size_of::<(usize, [u16; 2], [u64; 2 * BTREE_NODE_CAPACITY])>()
+        );
+        // Internal nodes add one more edge than they have entries.
+        assert_eq!(
+            internal,
+            leaf + (BTREE_NODE_CAPACITY + 1) * mem::size_of::<usize>()
+        );
+    }
+
+    #[test]
+    fn linked_list() {
+        assert_eq!(
+            linked_list_node_size::<u64>(),
+            8 + 2 * mem::size_of::<usize>()
+        );
+    }
+}
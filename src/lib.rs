@@ -23,9 +23,26 @@
 //! memory in use by specific instances of types at any point in time, without any
 //! changes to the way in which these types are constructed.
 //!
+//! ## `no_std` support
+//!
+//! This crate is `no_std`, and only requires the [`alloc`] crate. The `std` feature flag
+//! (enabled by default) adds impls for types that are only available in the standard
+//! library, such as `HashMap`, `HashSet`, `Mutex`, and `PathBuf`. The impls for the
+//! primitive types, and for `alloc` types such as `Vec`, `String`, `Box`, `VecDeque`,
+//! `BTreeMap`, and `BTreeSet`, are always available.
+//!
+//! To measure hash maps in a `no_std` environment, enable the `hashbrown` feature flag
+//! and use `hashbrown::HashMap`:
+//!
+//! ```toml
+//! [dependencies]
+//! memuse = { version = "0.2", default-features = false, features = ["hashbrown"] }
+//! ```
+//!
 //! ## Minimum Supported Rust Version
 //!
-//! Requires Rust **1.51** or newer.
+//! Requires Rust **1.60** or newer, with the default features. Some optional feature flags
+//! enable dependencies that require a newer Rust version.
 //!
 //! In the future, we reserve the right to change MSRV (i.e. MSRV is out-of-scope for this
 //! crate's SemVer guarantees), however when we do it will be accompanied by a minor
@@ -51,6 +68,11 @@
 //! assert_eq!(empty.len(), 0);
 //! assert_eq!(empty.dynamic_usage(), 400);
 //!
+//! // Clearing a collection doesn't free the memory it has allocated.
+//! let mut cleared = vec![7u64; 50];
+//! cleared.clear();
+//! assert_eq!(cleared.dynamic_usage(), 400);
+//!
 //! // For some types, we can't measure the exact memory usage, so we return a best
 //! // estimate. If you need precision, call `dynamic_usage_bounds` which returns a
 //! // lower bound, and (if known) an upper bound.
@@ -61,6 +83,7 @@
 //! ```
 
 #![no_std]
+#![cfg_attr(memuse_allocator_api, feature(allocator_api))]
 #![forbid(unsafe_code)]
 // Catch documentation errors caused by code changes.
 #![deny(broken_intra_doc_links)]
@@ -71,13 +94,93 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+/// Derive macro for [`DynamicUsage`] (behind the `derive` feature flag).
+///
+/// The dynamic memory usage of a struct is the sum of that of its fields, and the
+/// dynamic memory usage of an enum is that of the fields of its active variant. Fields
+/// marked with `#[memuse(skip)]` (such as borrowed fields, whose target is owned
+/// elsewhere) are not measured, and their types don't need to implement `DynamicUsage`.
+///
+/// ```
+/// use memuse::DynamicUsage;
+///
+/// #[derive(DynamicUsage)]
+/// struct Message {
+///     id: u64,
+///     tags: Vec<String>,
+/// }
+///
+/// let msg = Message {
+///     id: 7,
+///     tags: vec![String::from("urgent")],
+/// };
+/// assert_eq!(msg.dynamic_usage(), msg.tags.dynamic_usage());
+/// ```
+#[cfg(feature = "derive")]
+pub use memuse_derive::DynamicUsage;
+
+use alloc::alloc::Layout;
+use alloc::borrow::{Cow, ToOwned};
 use alloc::boxed::Box;
-use alloc::collections::{BinaryHeap, LinkedList, VecDeque};
+use alloc::collections::{BTreeMap, BTreeSet, BinaryHeap, LinkedList, VecDeque};
+use alloc::rc::Rc;
 use alloc::string::String;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::hash::BuildHasherDefault;
 use core::mem;
+use core::sync::atomic;
 
 /// Trait for measuring the dynamic memory usage of types.
+///
+/// `DynamicUsage` is object-safe, so values of unrelated types can be measured together
+/// through trait objects:
+///
+/// ```
+/// # #[cfg(feature = "std")]
+/// # {
+/// use std::collections::HashMap;
+/// use memuse::DynamicUsage;
+///
+/// let mut sessions: HashMap<u64, Vec<u8>> = HashMap::new();
+/// sessions.insert(7, vec![0; 100]);
+/// let sessions_usage = sessions.dynamic_usage();
+///
+/// let subsystems: Vec<Box<dyn DynamicUsage>> = vec![
+///     Box::new(sessions),
+///     Box::new(String::with_capacity(10)),
+/// ];
+/// let total: usize = subsystems.iter().map(|s| s.as_ref().dynamic_usage()).sum();
+/// assert_eq!(total, sessions_usage + 10);
+/// # }
+/// ```
+///
+/// To measure trait objects of your own trait, make `DynamicUsage` one of its
+/// supertraits. A boxed trait object then implements `DynamicUsage`, counting the size
+/// of the boxed value along with its dynamic memory usage:
+///
+/// ```
+/// use memuse::DynamicUsage;
+///
+/// trait Shape: DynamicUsage {}
+///
+/// impl Shape for Vec<(f32, f32)> {}
+/// impl Shape for String {}
+///
+/// let shapes: Vec<Box<dyn Shape>> = vec![
+///     Box::new(vec![(0.0, 0.0); 3]),
+///     Box::new(String::with_capacity(10)),
+/// ];
+/// assert_eq!(
+///     shapes.dynamic_usage(),
+///     shapes.capacity() * std::mem::size_of::<Box<dyn Shape>>()
+///         + std::mem::size_of::<Vec<(f32, f32)>>() + 3 * 8
+///         + std::mem::size_of::<String>() + 10,
+/// );
+/// ```
 pub trait DynamicUsage {
     /// Returns a best estimate of the amount of heap-allocated memory used by this type.
     ///
@@ -125,6 +228,645 @@ pub trait DynamicUsage {
     /// If the type's allocated memory is precisely known, then the lower and upper bounds
     /// will be equal.
     fn dynamic_usage_bounds(&self) -> (usize, Option<usize>);
+
+    /// Returns a best estimate of the amount of heap-allocated memory used by this type,
+    /// or `None` if it cannot be measured without blocking.
+    ///
+    /// Types that guard their contents behind a lock (such as `Mutex`) return `None` if
+    /// the lock is currently held elsewhere, instead of waiting for it to be released.
+    /// Collections return `None` if any of their elements do. For all other types, this
+    /// is equivalent to `Some(self.dynamic_usage())`.
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        Some(self.dynamic_usage())
+    }
+
+    /// Returns `true` if [`DynamicUsage::dynamic_usage`] may over-count the memory used by
+    /// this value, because some of its heap allocations may be shared with other values.
+    ///
+    /// Types that share allocations between clones (such as `ecow::EcoVec`, or
+    /// `bytes::Bytes`) count a shared allocation in full for every clone, so summing the
+    /// usage of several values can count the same allocation more than once. Containers
+    /// return `true` if any of their elements do. For all other types, this returns
+    /// `false`.
+    ///
+    /// `Rc` and `Arc` instead divide their allocation between their strong pointers, so
+    /// summing the usage of every clone counts it approximately once. They only return
+    /// `true` if their value does.
+    ///
+    /// ```
+    /// use memuse::DynamicUsage;
+    ///
+    /// let owned: Vec<Vec<u8>> = vec![vec![0; 10]; 3];
+    /// assert!(!owned.may_overcount());
+    /// ```
+    fn may_overcount(&self) -> bool {
+        false
+    }
+
+    /// Returns the dynamic memory usage of this value, counting each shared allocation
+    /// (such as the allocation behind an `Rc` or `Arc`) at most once.
+    ///
+    /// Shared allocations that are already recorded in `visited` are skipped, and those
+    /// reached for the first time are recorded and counted in full. Passing the same
+    /// [`Visited`] set when measuring several values counts the allocations shared
+    /// between them once in total.
+    ///
+    /// [`DynamicUsage::dynamic_usage`] never terminates for values that contain a
+    /// reference cycle (for example, `Rc<RefCell<Node>>` where nodes point to each
+    /// other), because it measures the contents of every strong pointer it reaches. This
+    /// method reaches each shared allocation once, so it terminates for cyclic values.
+    ///
+    /// ```
+    /// use std::rc::Rc;
+    /// use memuse::{DynamicUsage, Visited};
+    ///
+    /// let shared = Rc::new(vec![0u8; 100]);
+    /// let a = vec![shared.clone(), shared.clone()];
+    /// let b = vec![shared];
+    ///
+    /// let mut visited = Visited::new();
+    /// let a_usage = a.dedup_dynamic_usage(&mut visited);
+    /// let b_usage = b.dedup_dynamic_usage(&mut visited);
+    ///
+    /// // The shared vector is only counted while measuring `a`.
+    /// assert!(a_usage > 100);
+    /// assert_eq!(b_usage, b.capacity() * std::mem::size_of::<Rc<Vec<u8>>>());
+    /// ```
+    ///
+    /// Types that don't share allocations only need to implement this if they contain
+    /// values that might, in which case they should pass `visited` through to them. The
+    /// default implementation returns [`DynamicUsage::dynamic_usage`].
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        let _ = visited;
+        self.dynamic_usage()
+    }
+
+    /// Returns the total memory used by this value: its inline size (as returned by
+    /// [`mem::size_of_val`]) plus [`DynamicUsage::dynamic_usage`].
+    ///
+    /// See [`deep_usage`] for when to use this instead of `dynamic_usage`.
+    ///
+    /// ```
+    /// use core::mem;
+    /// use memuse::DynamicUsage;
+    ///
+    /// let buf = vec![0u8; 10];
+    /// assert_eq!(buf.total_usage(), mem::size_of::<Vec<u8>>() + 10);
+    /// ```
+    fn total_usage(&self) -> usize {
+        mem::size_of_val(self) + self.dynamic_usage()
+    }
+
+    /// Returns the amount of heap-allocated memory used by this value to hold live data,
+    /// excluding spare capacity.
+    ///
+    /// Collections count the memory occupied by their elements (based on their length
+    /// rather than their capacity), plus the memory used by each element. Comparing this
+    /// with [`DynamicUsage::dynamic_usage`] shows how much memory is held as spare
+    /// capacity. Unlike [`ContainerUsage::minimal_usage`], this doesn't count any
+    /// overhead that would remain after shrinking the collection (such as the control
+    /// bytes of a `HashMap`).
+    ///
+    /// ```
+    /// use memuse::DynamicUsage;
+    ///
+    /// let mut buf: Vec<u64> = Vec::with_capacity(10);
+    /// buf.push(7);
+    /// assert_eq!(buf.dynamic_usage(), 80);
+    /// assert_eq!(buf.dynamic_usage_used(), 8);
+    /// ```
+    ///
+    /// The default implementation returns [`DynamicUsage::dynamic_usage`].
+    fn dynamic_usage_used(&self) -> usize {
+        self.dynamic_usage()
+    }
+
+    /// Returns [`DynamicUsage::dynamic_usage`] wrapped in a type that formats it for
+    /// humans.
+    ///
+    /// ```
+    /// use memuse::DynamicUsage;
+    ///
+    /// let buf = vec![0u8; 3 * 1024 * 1024 / 2];
+    /// assert_eq!(buf.human_usage().to_string(), "1.50 MiB");
+    /// ```
+    fn human_usage(&self) -> HumanUsage {
+        HumanUsage(self.dynamic_usage())
+    }
+
+    /// Returns [`DynamicUsage::dynamic_usage`], along with the time taken to measure it.
+    ///
+    /// Measuring a large or deeply-nested value can be slow, because the usage of every
+    /// element is measured. This can help decide whether to cache the result, or measure
+    /// less frequently.
+    ///
+    /// ```
+    /// use memuse::DynamicUsage;
+    ///
+    /// let nested: Vec<Vec<u8>> = (0..1000).map(|i| vec![0; i]).collect();
+    /// let (usage, elapsed) = nested.timed_dynamic_usage();
+    /// assert_eq!(usage, nested.dynamic_usage());
+    /// println!("Measured {} bytes in {:?}", usage, elapsed);
+    /// ```
+    #[cfg(feature = "std")]
+    fn timed_dynamic_usage(&self) -> (usize, std::time::Duration) {
+        let start = std::time::Instant::now();
+        let usage = self.dynamic_usage();
+        (usage, start.elapsed())
+    }
+}
+
+/// Trait for inspecting the dynamic memory usage of the entries in a map.
+pub trait MapUsage {
+    /// Returns a histogram of the dynamic memory usage of the map's values.
+    ///
+    /// Values are grouped into power-of-two buckets by their
+    /// [`DynamicUsage::dynamic_usage`]: a value using `n > 0` bytes is placed in the
+    /// bucket `2^k <= n < 2^(k+1)`, and values that don't allocate are placed in bucket
+    /// `0`. The histogram maps the lower bound of each non-empty bucket to the number of
+    /// values in it.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// use std::collections::HashMap;
+    /// use memuse::MapUsage;
+    ///
+    /// let mut cache: HashMap<u32, Vec<u8>> = HashMap::new();
+    /// cache.insert(1, vec![]);
+    /// cache.insert(2, vec![0; 10]);
+    /// cache.insert(3, vec![0; 100]);
+    /// cache.insert(4, vec![0; 1000]);
+    /// cache.insert(5, vec![0; 1000]);
+    ///
+    /// let histogram = cache.value_size_histogram();
+    /// assert_eq!(
+    ///     histogram.into_iter().collect::<Vec<_>>(),
+    ///     [(0, 1), (8, 1), (64, 1), (512, 2)],
+    /// );
+    /// # }
+    /// ```
+    fn value_size_histogram(&self) -> BTreeMap<usize, usize>;
+
+    /// Returns the dynamic memory usage of each entry in the map (the sum of the key's
+    /// and the value's [`DynamicUsage::dynamic_usage`]), in the map's iteration order.
+    ///
+    /// This does not include the memory used by the map itself to store the entries.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// use std::collections::BTreeMap;
+    /// use memuse::MapUsage;
+    ///
+    /// let mut cache = BTreeMap::new();
+    /// cache.insert(String::from("a"), vec![0u8; 10]);
+    /// cache.insert(String::from("bb"), vec![0u8; 1000]);
+    /// cache.insert(String::from("ccc"), vec![0u8; 100]);
+    ///
+    /// assert_eq!(cache.entry_sizes(), [11, 1002, 103]);
+    /// # }
+    /// ```
+    fn entry_sizes(&self) -> Vec<usize>;
+
+    /// Returns the projected dynamic memory usage of the map after `additional` more
+    /// entries have been inserted into it.
+    ///
+    /// The projection models how the map's own storage grows to hold the new entries,
+    /// and includes the current dynamic memory usage of its existing entries. The
+    /// dynamic memory usage of the new entries themselves is unknown, and not included.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// use std::collections::HashMap;
+    /// use memuse::{DynamicUsage, MapUsage};
+    ///
+    /// let mut cache: HashMap<u32, u64> = HashMap::new();
+    /// let projected = cache.projected_usage(100);
+    ///
+    /// cache.extend((0..100).map(|i| (i, 0)));
+    /// assert_eq!(cache.dynamic_usage(), projected);
+    /// # }
+    /// ```
+    fn projected_usage(&self, additional: usize) -> usize;
+
+    /// Writes a JSON report of the dynamic memory usage of the map to `writer`.
+    ///
+    /// The report is an object with the following fields:
+    /// - `storage`: the dynamic memory usage of the map's own storage, including its
+    ///   hasher.
+    /// - `entries`: the dynamic memory usage of each entry, in the map's iteration order
+    ///   (as returned by [`MapUsage::entry_sizes`]).
+    /// - `total`: the sum of the above, which is the map's
+    ///   [`DynamicUsage::dynamic_usage`].
+    ///
+    /// The report is written while the map is traversed, rather than being built in
+    /// memory first, so reporting on a large map doesn't need memory proportional to its
+    /// size. Each entry is written separately, so `writer` should usually be buffered.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// use std::collections::BTreeMap;
+    /// use memuse::MapUsage;
+    ///
+    /// let mut cache = BTreeMap::new();
+    /// cache.insert(1u64, vec![0u8; 10]);
+    /// cache.insert(2u64, vec![0u8; 1000]);
+    ///
+    /// let mut report = Vec::new();
+    /// cache.write_usage_report(&mut report).unwrap();
+    /// let report = String::from_utf8(report).unwrap();
+    /// assert!(report.starts_with(r#"{"storage":"#));
+    /// assert!(report.contains(r#""entries":[10,1000]"#));
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    fn write_usage_report<W: std::io::Write>(&self, writer: W) -> std::io::Result<()>;
+}
+
+/// Trait for inspecting the shape of a B-tree.
+pub trait BTreeUsage {
+    /// Returns the lower and upper bounds on the height of the B-tree, as inferred from
+    /// the number of entries it contains.
+    ///
+    /// A tree consisting of a single leaf node has height 0. Every level of the tree adds
+    /// a node to the path traversed by a lookup.
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// use std::collections::BTreeMap;
+    /// use memuse::BTreeUsage;
+    ///
+    /// let map: BTreeMap<u32, u32> = (0..1000).map(|i| (i, i)).collect();
+    /// let (h_min, h_max) = map.estimated_height();
+    /// assert_eq!((h_min, h_max), (2, 3));
+    /// # }
+    /// ```
+    fn estimated_height(&self) -> (u32, u32);
+}
+
+/// Trait for inspecting how much of a container's dynamic memory usage is spare
+/// capacity.
+pub trait ContainerUsage: DynamicUsage {
+    /// Returns the dynamic memory usage that this container would have after calling its
+    /// `shrink_to_fit` method.
+    ///
+    /// Only the container's own storage is shrunk; the dynamic memory usage of its
+    /// elements is unchanged.
+    fn minimal_usage(&self) -> usize;
+
+    /// Returns the number of bytes that would be freed by calling this container's
+    /// `shrink_to_fit` method.
+    ///
+    /// ```
+    /// use memuse::{ContainerUsage, DynamicUsage};
+    ///
+    /// let mut buffer: Vec<u64> = Vec::with_capacity(100);
+    /// buffer.extend(0..10);
+    /// assert_eq!(buffer.shrink_savings(), 90 * 8);
+    ///
+    /// let minimal = buffer.minimal_usage();
+    /// buffer.shrink_to_fit();
+    /// assert_eq!(buffer.dynamic_usage(), minimal);
+    /// assert_eq!(buffer.shrink_savings(), 0);
+    /// ```
+    fn shrink_savings(&self) -> usize {
+        self.dynamic_usage().saturating_sub(self.minimal_usage())
+    }
+
+    /// Returns the fraction of this container's capacity that is occupied by elements,
+    /// between `0.0` and `1.0`.
+    ///
+    /// A container with no capacity is considered fully utilized.
+    ///
+    /// ```
+    /// use memuse::ContainerUsage;
+    ///
+    /// let mut buffer: Vec<u64> = Vec::new();
+    /// assert_eq!(buffer.utilization(), 1.0);
+    ///
+    /// buffer.reserve_exact(8);
+    /// buffer.extend(0..2);
+    /// assert_eq!(buffer.utilization(), 0.25);
+    /// ```
+    fn utilization(&self) -> f64;
+}
+
+/// Returns the fraction of `capacity` that is occupied by `len` elements.
+fn utilization(len: usize, capacity: usize) -> f64 {
+    if capacity == 0 {
+        1.0
+    } else {
+        len as f64 / capacity as f64
+    }
+}
+
+/// Returns the total dynamic memory usage of a collection of values of unrelated types.
+///
+/// ```
+/// # #[cfg(feature = "std")]
+/// # {
+/// use std::collections::HashMap;
+/// use memuse::{total_dynamic_usage, DynamicUsage};
+///
+/// let buffer: Vec<u64> = Vec::with_capacity(16);
+/// let mut index: HashMap<u64, usize> = HashMap::new();
+/// index.insert(7, 0);
+/// let name = String::from("subsystem");
+///
+/// let roots: [&dyn DynamicUsage; 3] = [&buffer, &index, &name];
+/// assert_eq!(
+///     total_dynamic_usage(roots),
+///     16 * 8 + index.dynamic_usage() + name.len(),
+/// );
+/// # }
+/// ```
+pub fn total_dynamic_usage<'a>(items: impl IntoIterator<Item = &'a dyn DynamicUsage>) -> usize {
+    items.into_iter().map(DynamicUsage::dynamic_usage).sum()
+}
+
+/// Returns the total memory used by `value`, including its inline size.
+///
+/// [`DynamicUsage::dynamic_usage`] doesn't count the inline size of a value (such as the
+/// pointer, capacity, and length of a `Vec<T>`), because it is part of the memory of
+/// whatever owns the value: a stack frame, or a containing value whose own usage already
+/// includes it. Use `dynamic_usage` when measuring a value that you own directly, or when
+/// implementing [`DynamicUsage`] for a type that contains it.
+///
+/// Use `deep_usage` when the value itself is the thing being accounted for, such as when
+/// it was allocated separately from its owner (for example, the `Vec<T>` inside a
+/// `Box<Vec<T>>`, or a value behind a pointer in a custom container). It is equivalent to
+/// [`DynamicUsage::total_usage`].
+///
+/// ```
+/// use core::mem;
+/// use memuse::{deep_usage, DynamicUsage};
+///
+/// let buf: Vec<u64> = Vec::with_capacity(4);
+/// assert_eq!(buf.dynamic_usage(), 32);
+/// assert_eq!(deep_usage(&buf), mem::size_of::<Vec<u64>>() + 32);
+///
+/// // Boxing the `Vec` moves its header onto the heap, where the box counts it.
+/// let boxed = Box::new(buf);
+/// assert_eq!(boxed.dynamic_usage(), deep_usage(&*boxed));
+/// ```
+pub fn deep_usage<T: DynamicUsage + ?Sized>(value: &T) -> usize {
+    T::total_usage(value)
+}
+
+/// Measures the values produced by an iterator, without collecting them.
+///
+/// Returns the sum of the values' [`DynamicUsage::dynamic_usage`], along with the sum of
+/// their [`DynamicUsage::dynamic_usage_bounds`].
+///
+/// The iterator must produce owned values. To measure values through references (such
+/// as those produced by `vec.iter()`), pass `vec.iter().map(DynamicUsage::dynamic_usage)`
+/// to [`Iterator::sum`] instead.
+///
+/// ```
+/// use memuse::measure_iter;
+///
+/// let (usage, bounds) = measure_iter((0..100).map(|i| vec![0u8; i]));
+/// assert_eq!(usage, 4950);
+/// assert_eq!(bounds, (4950, Some(4950)));
+/// ```
+pub fn measure_iter<T: DynamicUsage>(
+    items: impl IntoIterator<Item = T>,
+) -> (usize, (usize, Option<usize>)) {
+    items.into_iter().fold(
+        (0, (0, Some(0))),
+        |(acc_usage, (acc_lower, acc_upper)), item| {
+            let (lower, upper) = item.dynamic_usage_bounds();
+            (
+                acc_usage + item.dynamic_usage(),
+                (acc_lower + lower, acc_upper.zip(upper).map(|(a, b)| a + b)),
+            )
+        },
+    )
+}
+
+/// The set of shared allocations that have been measured by
+/// [`DynamicUsage::dedup_dynamic_usage`].
+#[derive(Clone, Debug, Default)]
+pub struct Visited(BTreeSet<usize>);
+
+impl Visited {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Visited(BTreeSet::new())
+    }
+
+    /// Records the shared allocation at `ptr`, and returns `true` if it was not already
+    /// recorded.
+    pub fn insert<T: ?Sized>(&mut self, ptr: *const T) -> bool {
+        self.0.insert(ptr as *const () as usize)
+    }
+
+    /// Returns the number of shared allocations that have been recorded.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if no shared allocations have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Returns the largest number of entries that a `HashMap<K, V>` can hold without its
+/// table allocation exceeding `budget` bytes.
+///
+/// The number of buckets in a hash table grows in powers of two, so the returned number
+/// of entries is that of the largest table that fits within the budget. The dynamic
+/// memory usage of the entries themselves is not included.
+///
+/// ```
+/// # #[cfg(feature = "std")]
+/// # {
+/// use std::collections::HashMap;
+/// use memuse::{entries_within_budget, DynamicUsage};
+///
+/// let entries = entries_within_budget::<u64, u64>(64 * 1024);
+/// let map: HashMap<u64, u64> = HashMap::with_capacity(entries);
+/// assert!(map.dynamic_usage() <= 64 * 1024);
+/// # }
+/// ```
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub fn entries_within_budget<K, V>(budget: usize) -> usize {
+    hash::entries_within_budget::<K, V>(budget)
+}
+
+/// A number of bytes, which is displayed using binary (1024-based) units.
+///
+/// ```
+/// use memuse::HumanUsage;
+///
+/// assert_eq!(HumanUsage(0).to_string(), "0 B");
+/// assert_eq!(HumanUsage(1000).to_string(), "1000 B");
+/// assert_eq!(HumanUsage(1024).to_string(), "1.00 KiB");
+/// assert_eq!(HumanUsage(1_234_567).to_string(), "1.18 MiB");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HumanUsage(pub usize);
+
+impl fmt::Display for HumanUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+
+        let mut value = self.0 as f64 / 1024.0;
+        let mut unit = 0;
+        // Choose the unit after rounding to two decimal places, so that values just
+        // below a unit boundary aren't displayed as 1024.00 of the smaller unit. (`f64::round`
+        // requires `std`, so round the non-negative value by hand.)
+        while (value * 100.0 + 0.5) as u64 >= 1024 * 100 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        write!(f, "{:.2} {}", value, UNITS[unit])
+    }
+}
+
+/// A guard that tracks how the dynamic memory usage of a value changes while it is
+/// borrowed.
+///
+/// The guard dereferences to the borrowed value, so the value can be modified through
+/// it, and [`UsageGuard::delta`] returns the change so far. When the guard is dropped,
+/// the change is reported:
+/// - A guard created with [`UsageGuard::with_report`] passes the initial and final
+///   dynamic memory usage to its callback.
+/// - A guard created with [`UsageGuard::new`] logs the change at debug level, if the
+///   `log` feature flag is enabled. Otherwise, it reports nothing.
+///
+/// ```
+/// use memuse::UsageGuard;
+///
+/// let mut cache: Vec<u64> = Vec::new();
+/// let mut change = 0;
+/// {
+///     let mut guard = UsageGuard::with_report(&mut cache, |initial, usage| {
+///         change = usage - initial;
+///     });
+///     guard.reserve_exact(10);
+///     assert_eq!(guard.delta(), 80);
+/// }
+/// assert_eq!(change, 80);
+/// ```
+pub struct UsageGuard<'a, T: DynamicUsage> {
+    value: &'a mut T,
+    initial_usage: usize,
+    report: Option<Box<dyn FnOnce(usize, usize) + 'a>>,
+}
+
+impl<'a, T: DynamicUsage> UsageGuard<'a, T> {
+    /// Starts tracking the dynamic memory usage of `value`.
+    ///
+    /// The change is only reported when the guard is dropped if the `log` feature flag is
+    /// enabled. Use [`UsageGuard::with_report`] to report it without `log`.
+    pub fn new(value: &'a mut T) -> Self {
+        let initial_usage = value.dynamic_usage();
+        UsageGuard {
+            value,
+            initial_usage,
+            report: None,
+        }
+    }
+
+    /// Starts tracking the dynamic memory usage of `value`, and calls `report` with its
+    /// initial and final dynamic memory usage when the guard is dropped.
+    pub fn with_report(value: &'a mut T, report: impl FnOnce(usize, usize) + 'a) -> Self {
+        let initial_usage = value.dynamic_usage();
+        UsageGuard {
+            value,
+            initial_usage,
+            report: Some(Box::new(report)),
+        }
+    }
+
+    /// Returns the dynamic memory usage of the value when the guard was created.
+    pub fn initial_usage(&self) -> usize {
+        self.initial_usage
+    }
+
+    /// Returns the change in the dynamic memory usage of the value since the guard was
+    /// created.
+    pub fn delta(&self) -> isize {
+        self.value.dynamic_usage() as isize - self.initial_usage as isize
+    }
+}
+
+impl<'a, T: DynamicUsage> core::ops::Deref for UsageGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: DynamicUsage> core::ops::DerefMut for UsageGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<'a, T: DynamicUsage> Drop for UsageGuard<'a, T> {
+    fn drop(&mut self) {
+        if let Some(report) = self.report.take() {
+            report(self.initial_usage, self.value.dynamic_usage());
+        } else {
+            #[cfg(feature = "log")]
+            {
+                let usage = self.value.dynamic_usage();
+                log::debug!(
+                    "Dynamic memory usage of {} changed by {} bytes ({} -> {})",
+                    core::any::type_name::<T>(),
+                    usage as isize - self.initial_usage as isize,
+                    HumanUsage(self.initial_usage),
+                    HumanUsage(usage),
+                );
+            }
+        }
+    }
+}
+
+/// Groups the given sizes into power-of-two buckets, as described in
+/// [`MapUsage::value_size_histogram`].
+pub(crate) fn size_histogram(sizes: impl Iterator<Item = usize>) -> BTreeMap<usize, usize> {
+    let mut histogram = BTreeMap::new();
+    for size in sizes {
+        let bucket = match size {
+            0 => 0,
+            _ => 1 << (mem::size_of::<usize>() * 8 - 1 - size.leading_zeros() as usize),
+        };
+        *histogram.entry(bucket).or_insert(0) += 1;
+    }
+    histogram
+}
+
+/// Writes a map usage report, in the format described in
+/// [`MapUsage::write_usage_report`].
+#[cfg(feature = "std")]
+pub(crate) fn write_map_usage_report<W: std::io::Write>(
+    mut writer: W,
+    storage: usize,
+    entries: impl Iterator<Item = usize>,
+) -> std::io::Result<()> {
+    write!(writer, "{{\"storage\":{},\"entries\":[", storage)?;
+    let mut total = storage;
+    for (i, size) in entries.enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        write!(writer, "{}", size)?;
+        total += size;
+    }
+    write!(writer, "],\"total\":{}}}", total)
 }
 
 //
@@ -196,8 +938,11 @@ macro_rules! impl_no_dynamic_usage {
 }
 
 macro_rules! impl_iterable_dynamic_usage {
-    ($type:ty, $base_usage:expr) => {
-        impl<T: DynamicUsage> DynamicUsage for $type {
+    ([$($generics:tt)*] $type:ty, $base_usage:expr) => {
+        impl_iterable_dynamic_usage!([$($generics)*] $type, $base_usage, $base_usage);
+    };
+    ([$($generics:tt)*] $type:ty, $base_usage:expr, $used_usage:expr) => {
+        impl<T: DynamicUsage, $($generics)*> DynamicUsage for $type {
             fn dynamic_usage(&self) -> usize {
                 $base_usage(self) + self.iter().map(DynamicUsage::dynamic_usage).sum::<usize>()
             }
@@ -212,8 +957,37 @@ macro_rules! impl_iterable_dynamic_usage {
                 );
                 (base + lower, upper.map(|u| base + u))
             }
-        }
-    };
+
+            fn try_dynamic_usage(&self) -> Option<usize> {
+                self.iter().try_fold($base_usage(self), |acc, item| {
+                    Some(acc + item.try_dynamic_usage()?)
+                })
+            }
+
+            fn may_overcount(&self) -> bool {
+                self.iter().any(DynamicUsage::may_overcount)
+            }
+
+            fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+                $base_usage(self)
+                    + self
+                        .iter()
+                        .map(|item| item.dedup_dynamic_usage(visited))
+                        .sum::<usize>()
+            }
+
+            fn dynamic_usage_used(&self) -> usize {
+                $used_usage(self)
+                    + self
+                        .iter()
+                        .map(DynamicUsage::dynamic_usage_used)
+                        .sum::<usize>()
+            }
+        }
+    };
+    ($type:ty, $base_usage:expr $(, $used_usage:expr)?) => {
+        impl_iterable_dynamic_usage!([] $type, $base_usage $(, $used_usage)?);
+    };
 }
 
 //
@@ -225,6 +999,125 @@ impl_no_dynamic_usage!(i8, i16, i32, i64, i128, isize);
 impl_no_dynamic_usage!(u8, u16, u32, u64, u128, usize);
 impl_no_dynamic_usage!(f32, f64, bool);
 impl_no_dynamic_usage!(char, str);
+impl_no_dynamic_usage!(core::time::Duration);
+#[cfg(feature = "std")]
+impl_no_dynamic_usage!(std::time::Instant, std::time::SystemTime);
+#[cfg(feature = "std")]
+impl_no_dynamic_usage!(
+    std::net::IpAddr,
+    std::net::Ipv4Addr,
+    std::net::Ipv6Addr,
+    std::net::SocketAddr,
+    std::net::SocketAddrV4,
+    std::net::SocketAddrV6
+);
+impl_no_dynamic_usage!(
+    core::num::NonZeroI8,
+    core::num::NonZeroI16,
+    core::num::NonZeroI32,
+    core::num::NonZeroI64,
+    core::num::NonZeroI128,
+    core::num::NonZeroIsize
+);
+impl_no_dynamic_usage!(
+    core::num::NonZeroU8,
+    core::num::NonZeroU16,
+    core::num::NonZeroU32,
+    core::num::NonZeroU64,
+    core::num::NonZeroU128,
+    core::num::NonZeroUsize
+);
+
+impl_no_dynamic_usage!(core::marker::PhantomPinned);
+
+impl<T: ?Sized> DynamicUsage for core::marker::PhantomData<T> {
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        0
+    }
+
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
+
+impl<T: DynamicUsage> DynamicUsage for core::num::Wrapping<T> {
+    fn dynamic_usage(&self) -> usize {
+        self.0.dynamic_usage()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        self.0.dynamic_usage_bounds()
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.0.try_dynamic_usage()
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.0.may_overcount()
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.0.dedup_dynamic_usage(visited)
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        self.0.dynamic_usage_used()
+    }
+}
+
+impl<T: DynamicUsage> DynamicUsage for core::cmp::Reverse<T> {
+    fn dynamic_usage(&self) -> usize {
+        self.0.dynamic_usage()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        self.0.dynamic_usage_bounds()
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.0.try_dynamic_usage()
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.0.may_overcount()
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.0.dedup_dynamic_usage(visited)
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        self.0.dynamic_usage_used()
+    }
+}
+
+#[cfg(target_has_atomic = "8")]
+impl_no_dynamic_usage!(atomic::AtomicBool, atomic::AtomicI8, atomic::AtomicU8);
+#[cfg(target_has_atomic = "16")]
+impl_no_dynamic_usage!(atomic::AtomicI16, atomic::AtomicU16);
+#[cfg(target_has_atomic = "32")]
+impl_no_dynamic_usage!(atomic::AtomicI32, atomic::AtomicU32);
+#[cfg(target_has_atomic = "64")]
+impl_no_dynamic_usage!(atomic::AtomicI64, atomic::AtomicU64);
+#[cfg(target_has_atomic = "ptr")]
+impl_no_dynamic_usage!(atomic::AtomicIsize, atomic::AtomicUsize);
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T> DynamicUsage for atomic::AtomicPtr<T> {
+    // The pointee is not owned by the AtomicPtr.
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        0
+    }
+
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
 
 // Tuples are handled below (so they render more nicely in docs)
 
@@ -241,6 +1134,27 @@ impl<T: DynamicUsage, const N: usize> DynamicUsage for [T; N] {
             },
         )
     }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.iter()
+            .try_fold(0, |acc, item| Some(acc + item.try_dynamic_usage()?))
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.iter().any(DynamicUsage::may_overcount)
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.iter()
+            .map(|item| item.dedup_dynamic_usage(visited))
+            .sum::<usize>()
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        self.iter()
+            .map(DynamicUsage::dynamic_usage_used)
+            .sum::<usize>()
+    }
 }
 
 impl_iterable_dynamic_usage!([T], |_| 0);
@@ -258,185 +1172,2700 @@ impl DynamicUsage for String {
         let usage = self.capacity();
         (usage, Some(usage))
     }
+
+    fn dynamic_usage_used(&self) -> usize {
+        self.len()
+    }
 }
 
-//
-// Containers
-//
+#[cfg(feature = "std")]
+impl DynamicUsage for std::ffi::CString {
+    fn dynamic_usage(&self) -> usize {
+        // A CString owns a boxed slice that includes the trailing nul byte.
+        self.as_bytes_with_nul().len()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let usage = self.dynamic_usage();
+        (usage, Some(usage))
+    }
+}
 
-impl<T: DynamicUsage> DynamicUsage for Box<T> {
+#[cfg(feature = "std")]
+impl DynamicUsage for std::ffi::OsString {
     fn dynamic_usage(&self) -> usize {
-        mem::size_of::<T>() + self.as_ref().dynamic_usage()
+        self.capacity()
     }
 
     fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
-        let box_size = mem::size_of::<T>();
-        let (inner_lower, inner_upper) = self.as_ref().dynamic_usage_bounds();
-        (box_size + inner_lower, inner_upper.map(|u| box_size + u))
+        let usage = self.capacity();
+        (usage, Some(usage))
     }
 }
 
-impl<T: DynamicUsage> DynamicUsage for Option<T> {
+#[cfg(feature = "std")]
+impl_no_dynamic_usage!(std::ffi::CStr, std::ffi::OsStr);
+
+#[cfg(feature = "std")]
+impl DynamicUsage for std::path::PathBuf {
     fn dynamic_usage(&self) -> usize {
-        self.as_ref().map(DynamicUsage::dynamic_usage).unwrap_or(0)
+        self.capacity()
     }
 
     fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
-        self.as_ref()
-            .map(DynamicUsage::dynamic_usage_bounds)
-            .unwrap_or((0, Some(0)))
+        let usage = self.capacity();
+        (usage, Some(usage))
     }
 }
 
-impl<T: DynamicUsage, E: DynamicUsage> DynamicUsage for Result<T, E> {
+#[cfg(feature = "std")]
+impl_no_dynamic_usage!(std::path::Path);
+
+/// The cursor's position is stored inline, so only the wrapped buffer is measured.
+#[cfg(feature = "std")]
+impl<T: DynamicUsage> DynamicUsage for std::io::Cursor<T> {
     fn dynamic_usage(&self) -> usize {
-        match self {
-            Ok(t) => t.dynamic_usage(),
-            Err(e) => e.dynamic_usage(),
-        }
+        self.get_ref().dynamic_usage()
     }
 
     fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
-        match self {
-            Ok(t) => t.dynamic_usage_bounds(),
-            Err(e) => e.dynamic_usage_bounds(),
-        }
+        self.get_ref().dynamic_usage_bounds()
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.get_ref().try_dynamic_usage()
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.get_ref().may_overcount()
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.get_ref().dedup_dynamic_usage(visited)
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        self.get_ref().dynamic_usage_used()
     }
 }
 
-//
-// Collections
-//
+impl<H> DynamicUsage for BuildHasherDefault<H> {
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        0
+    }
 
-impl_iterable_dynamic_usage!(Vec<T>, |c: &Vec<T>| c.capacity() * mem::size_of::<T>());
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
 
-impl_iterable_dynamic_usage!(BinaryHeap<T>, |c: &BinaryHeap<T>| {
-    // BinaryHeap<T> is a wrapper around Vec<T>
-    c.capacity() * mem::size_of::<T>()
-});
+fn add_bounds(a: (usize, Option<usize>), b: (usize, Option<usize>)) -> (usize, Option<usize>) {
+    (a.0 + b.0, a.1.zip(b.1).map(|(a, b)| a + b))
+}
 
-impl_iterable_dynamic_usage!(LinkedList<T>, |c: &LinkedList<T>| {
-    c.len() * mem::size_of::<T>()
-});
+impl<T: DynamicUsage> DynamicUsage for core::ops::Range<T> {
+    fn dynamic_usage(&self) -> usize {
+        self.start.dynamic_usage() + self.end.dynamic_usage()
+    }
 
-impl_iterable_dynamic_usage!(VecDeque<T>, |c: &VecDeque<T>| {
-    // +1 since the ringbuffer always leaves one space empty.
-    (c.capacity() + 1) * mem::size_of::<T>()
-});
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        add_bounds(
+            self.start.dynamic_usage_bounds(),
+            self.end.dynamic_usage_bounds(),
+        )
+    }
 
-#[cfg(feature = "std")]
-mod hash;
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        Some(self.start.try_dynamic_usage()? + self.end.try_dynamic_usage()?)
+    }
 
-//
-// External crate types (provided for helpfulness, since `DynamicUsage` can only be
-// implemented either here or in the external crate).
-//
+    fn may_overcount(&self) -> bool {
+        self.start.may_overcount() || self.end.may_overcount()
+    }
 
-#[cfg(feature = "nonempty")]
-impl_iterable_dynamic_usage!(nonempty::NonEmpty<T>, |c: &nonempty::NonEmpty<T>| {
-    // NonEmpty<T> stores its head element separately from its tail Vec<T>.
-    (c.capacity() - 1) * mem::size_of::<T>()
-});
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.start.dedup_dynamic_usage(visited) + self.end.dedup_dynamic_usage(visited)
+    }
 
-//
-// Larger definitions (placed at the end so they render more nicely in docs).
-//
+    fn dynamic_usage_used(&self) -> usize {
+        self.start.dynamic_usage_used() + self.end.dynamic_usage_used()
+    }
+}
 
-mod tuple;
+impl<T: DynamicUsage> DynamicUsage for core::ops::RangeInclusive<T> {
+    fn dynamic_usage(&self) -> usize {
+        self.start().dynamic_usage() + self.end().dynamic_usage()
+    }
 
-#[cfg(test)]
-mod tests {
-    use alloc::string::ToString;
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        add_bounds(
+            self.start().dynamic_usage_bounds(),
+            self.end().dynamic_usage_bounds(),
+        )
+    }
 
-    use super::*;
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        Some(self.start().try_dynamic_usage()? + self.end().try_dynamic_usage()?)
+    }
 
-    #[test]
-    fn standard_types() {
-        assert_eq!(129u8.dynamic_usage(), 0);
-        assert_eq!(3i128.dynamic_usage(), 0);
-        assert_eq!(7.0f32.dynamic_usage(), 0);
-        assert_eq!("foobar".dynamic_usage(), 0);
+    fn may_overcount(&self) -> bool {
+        self.start().may_overcount() || self.end().may_overcount()
+    }
 
-        assert_eq!(129u8.dynamic_usage_bounds(), (0, Some(0)));
-        assert_eq!(3i128.dynamic_usage_bounds(), (0, Some(0)));
-        assert_eq!(7.0f32.dynamic_usage_bounds(), (0, Some(0)));
-        assert_eq!("foobar".dynamic_usage_bounds(), (0, Some(0)));
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.start().dedup_dynamic_usage(visited) + self.end().dedup_dynamic_usage(visited)
     }
 
-    #[test]
-    fn string() {
-        assert_eq!(String::new().dynamic_usage(), 0);
-        assert_eq!("foobar".to_string().dynamic_usage(), 6);
+    fn dynamic_usage_used(&self) -> usize {
+        self.start().dynamic_usage_used() + self.end().dynamic_usage_used()
+    }
+}
 
-        assert_eq!(String::new().dynamic_usage_bounds(), (0, Some(0)));
-        assert_eq!("foobar".to_string().dynamic_usage_bounds(), (6, Some(6)));
+impl<T: DynamicUsage> DynamicUsage for core::ops::RangeFrom<T> {
+    fn dynamic_usage(&self) -> usize {
+        self.start.dynamic_usage()
     }
 
-    #[test]
-    fn boxed() {
-        let a: u64 = 7;
-        assert_eq!(a.dynamic_usage(), 0);
-        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        self.start.dynamic_usage_bounds()
+    }
 
-        let b: Box<u64> = Box::new(42);
-        assert_eq!(b.dynamic_usage(), 8);
-        assert_eq!(b.dynamic_usage_bounds(), (8, Some(8)));
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.start.try_dynamic_usage()
+    }
 
-        let capacity = 7;
-        let c: Box<Vec<u16>> = Box::new(Vec::with_capacity(capacity));
-        let expected = mem::size_of::<Vec<u16>>() + capacity * mem::size_of::<u16>();
-        assert_eq!(c.dynamic_usage(), expected);
-        assert_eq!(c.dynamic_usage_bounds(), (expected, Some(expected)));
+    fn may_overcount(&self) -> bool {
+        self.start.may_overcount()
     }
 
-    #[test]
-    fn option() {
-        let a: Option<Vec<u8>> = None;
-        let b: Option<Vec<u8>> = Some(vec![7u8; 4]);
-        assert_eq!(a.dynamic_usage(), 0);
-        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
-        assert_eq!(b.dynamic_usage(), 4);
-        assert_eq!(b.dynamic_usage_bounds(), (4, Some(4)));
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.start.dedup_dynamic_usage(visited)
     }
 
-    #[test]
-    fn array() {
-        let a = [7; 42];
-        assert_eq!(a.dynamic_usage(), 0);
-        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+    fn dynamic_usage_used(&self) -> usize {
+        self.start.dynamic_usage_used()
+    }
+}
 
-        let mut b = [None, None, None, None];
-        assert_eq!(b.dynamic_usage(), 0);
-        assert_eq!(b.dynamic_usage_bounds(), (0, Some(0)));
+impl<T: DynamicUsage> DynamicUsage for core::ops::RangeTo<T> {
+    fn dynamic_usage(&self) -> usize {
+        self.end.dynamic_usage()
+    }
 
-        b[0] = Some(vec![4u8; 20]);
-        assert_eq!(b.dynamic_usage(), 20);
-        assert_eq!(b.dynamic_usage_bounds(), (20, Some(20)));
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        self.end.dynamic_usage_bounds()
     }
 
-    #[test]
-    fn vec() {
-        let capacity = 7;
-        let mut a = Vec::with_capacity(capacity);
-        a.push(42u64);
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.end.try_dynamic_usage()
+    }
 
-        let expected = capacity * mem::size_of::<u64>();
-        assert_eq!(a.dynamic_usage(), expected);
-        assert_eq!(a.dynamic_usage_bounds(), (expected, Some(expected)));
+    fn may_overcount(&self) -> bool {
+        self.end.may_overcount()
     }
 
-    #[cfg(feature = "nonempty")]
-    #[test]
-    fn nonempty() {
-        let a = nonempty::NonEmpty::new(42);
-        assert_eq!(a.dynamic_usage(), 0);
-        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.end.dedup_dynamic_usage(visited)
+    }
 
-        const CAPACITY: usize = 7;
-        let b = nonempty::NonEmpty::from_slice(&[27u128; CAPACITY]).unwrap();
+    fn dynamic_usage_used(&self) -> usize {
+        self.end.dynamic_usage_used()
+    }
+}
 
-        let expected = (CAPACITY - 1) * mem::size_of::<u128>();
-        assert_eq!(b.dynamic_usage(), expected);
-        assert_eq!(b.dynamic_usage_bounds(), (expected, Some(expected)));
+impl<T: DynamicUsage> DynamicUsage for core::ops::Bound<T> {
+    fn dynamic_usage(&self) -> usize {
+        match self {
+            core::ops::Bound::Included(t) | core::ops::Bound::Excluded(t) => t.dynamic_usage(),
+            core::ops::Bound::Unbounded => 0,
+        }
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        match self {
+            core::ops::Bound::Included(t) | core::ops::Bound::Excluded(t) => {
+                t.dynamic_usage_bounds()
+            }
+            core::ops::Bound::Unbounded => (0, Some(0)),
+        }
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        match self {
+            core::ops::Bound::Included(t) | core::ops::Bound::Excluded(t) => t.try_dynamic_usage(),
+            core::ops::Bound::Unbounded => Some(0),
+        }
+    }
+
+    fn may_overcount(&self) -> bool {
+        match self {
+            core::ops::Bound::Included(t) | core::ops::Bound::Excluded(t) => t.may_overcount(),
+            core::ops::Bound::Unbounded => false,
+        }
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        match self {
+            core::ops::Bound::Included(t) | core::ops::Bound::Excluded(t) => {
+                t.dedup_dynamic_usage(visited)
+            }
+            core::ops::Bound::Unbounded => 0,
+        }
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        match self {
+            core::ops::Bound::Included(t) | core::ops::Bound::Excluded(t) => t.dynamic_usage_used(),
+            core::ops::Bound::Unbounded => 0,
+        }
+    }
+}
+
+//
+// Containers
+//
+
+macro_rules! impl_box_dynamic_usage {
+    ([$($generics:tt)*] $type:ty) => {
+        /// The box allocates `mem::size_of_val` bytes for its contents. For a boxed slice
+        /// (such as `Box<[T]>` or `Box<str>`) this is the length of the slice, as boxed
+        /// slices have no spare capacity.
+        impl<T: DynamicUsage + ?Sized, $($generics)*> DynamicUsage for $type {
+            fn dynamic_usage(&self) -> usize {
+                mem::size_of_val(self.as_ref()) + self.as_ref().dynamic_usage()
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+                let box_size = mem::size_of_val(self.as_ref());
+                let (inner_lower, inner_upper) = self.as_ref().dynamic_usage_bounds();
+                (box_size + inner_lower, inner_upper.map(|u| box_size + u))
+            }
+
+            fn try_dynamic_usage(&self) -> Option<usize> {
+                Some(mem::size_of_val(self.as_ref()) + self.as_ref().try_dynamic_usage()?)
+            }
+
+            fn may_overcount(&self) -> bool {
+                self.as_ref().may_overcount()
+            }
+
+            fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+                mem::size_of_val(self.as_ref()) + self.as_ref().dedup_dynamic_usage(visited)
+            }
+
+            fn dynamic_usage_used(&self) -> usize {
+                mem::size_of_val(self.as_ref()) + self.as_ref().dynamic_usage_used()
+            }
+        }
+    };
+}
+
+#[cfg(not(memuse_allocator_api))]
+impl_box_dynamic_usage!([] Box<T>);
+#[cfg(memuse_allocator_api)]
+impl_box_dynamic_usage!([A: core::alloc::Allocator] Box<T, A>);
+
+impl<T: DynamicUsage> DynamicUsage for Option<T> {
+    fn dynamic_usage(&self) -> usize {
+        self.as_ref().map(DynamicUsage::dynamic_usage).unwrap_or(0)
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        self.as_ref()
+            .map(DynamicUsage::dynamic_usage_bounds)
+            .unwrap_or((0, Some(0)))
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.as_ref()
+            .map(DynamicUsage::try_dynamic_usage)
+            .unwrap_or(Some(0))
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.as_ref().map_or(false, DynamicUsage::may_overcount)
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.as_ref()
+            .map_or(0, |inner| inner.dedup_dynamic_usage(visited))
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        self.as_ref().map_or(0, DynamicUsage::dynamic_usage_used)
+    }
+}
+
+impl<T: DynamicUsage, E: DynamicUsage> DynamicUsage for Result<T, E> {
+    fn dynamic_usage(&self) -> usize {
+        match self {
+            Ok(t) => t.dynamic_usage(),
+            Err(e) => e.dynamic_usage(),
+        }
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        match self {
+            Ok(t) => t.dynamic_usage_bounds(),
+            Err(e) => e.dynamic_usage_bounds(),
+        }
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        match self {
+            Ok(t) => t.try_dynamic_usage(),
+            Err(e) => e.try_dynamic_usage(),
+        }
+    }
+
+    fn may_overcount(&self) -> bool {
+        match self {
+            Ok(t) => t.may_overcount(),
+            Err(e) => e.may_overcount(),
+        }
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        match self {
+            Ok(t) => t.dedup_dynamic_usage(visited),
+            Err(e) => e.dedup_dynamic_usage(visited),
+        }
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        match self {
+            Ok(t) => t.dynamic_usage_used(),
+            Err(e) => e.dynamic_usage_used(),
+        }
+    }
+}
+
+impl<B: ToOwned + ?Sized> DynamicUsage for Cow<'_, B>
+where
+    B::Owned: DynamicUsage,
+{
+    fn dynamic_usage(&self) -> usize {
+        match self {
+            // A borrowed value is owned (and measured) elsewhere.
+            Cow::Borrowed(_) => 0,
+            Cow::Owned(o) => o.dynamic_usage(),
+        }
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        match self {
+            Cow::Borrowed(_) => (0, Some(0)),
+            Cow::Owned(o) => o.dynamic_usage_bounds(),
+        }
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        match self {
+            Cow::Borrowed(_) => Some(0),
+            Cow::Owned(o) => o.try_dynamic_usage(),
+        }
+    }
+
+    fn may_overcount(&self) -> bool {
+        match self {
+            Cow::Borrowed(_) => false,
+            Cow::Owned(o) => o.may_overcount(),
+        }
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        match self {
+            Cow::Borrowed(_) => 0,
+            Cow::Owned(o) => o.dedup_dynamic_usage(visited),
+        }
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        match self {
+            Cow::Borrowed(_) => 0,
+            Cow::Owned(o) => o.dynamic_usage_used(),
+        }
+    }
+}
+
+/// Returns the size of the shared allocation backing an `Rc` or `Arc` to `value`.
+fn rc_allocation_size<T: ?Sized>(value: &T) -> usize {
+    // The allocation starts with the strong and weak reference counts, followed by the
+    // value.
+    Layout::new::<[usize; 2]>()
+        .extend(Layout::for_value(value))
+        .map(|(layout, _)| layout.pad_to_align().size())
+        .expect("value is already allocated")
+}
+
+/// Implements `DynamicUsage` for a reference-counted pointer type.
+macro_rules! impl_rc_dynamic_usage {
+    ($rc:ident) => {
+        /// The shared allocation (including the value's own dynamic memory usage) is
+        /// divided evenly between the strong pointers to it, so that summing the usage of
+        /// every clone counts the allocation approximately once. This is an
+        /// approximation: if some of the clones are not measured (for example, because
+        /// they are owned by a different structure), the allocation is under-counted.
+        ///
+        /// [`DynamicUsage::dedup_dynamic_usage`] instead counts the whole allocation the
+        /// first time it is reached, and nothing for every later pointer to it.
+        ///
+        /// Unsized values are stored inline in the shared allocation: for example, the
+        /// allocation behind an interned string `Rc<str>` holds the reference counts
+        /// followed by the string's bytes.
+        impl<T: DynamicUsage + ?Sized> DynamicUsage for $rc<T> {
+            fn dynamic_usage(&self) -> usize {
+                (rc_allocation_size(self.as_ref()) + self.as_ref().dynamic_usage())
+                    / $rc::strong_count(self)
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+                // This pointer's share of the allocation is a lower bound. If every other
+                // strong pointer is dropped, this pointer owns the whole allocation.
+                let size = rc_allocation_size(self.as_ref());
+                let (inner_lower, inner_upper) = self.as_ref().dynamic_usage_bounds();
+                (
+                    (size + inner_lower) / $rc::strong_count(self),
+                    inner_upper.map(|u| size + u),
+                )
+            }
+
+            fn try_dynamic_usage(&self) -> Option<usize> {
+                Some(
+                    (rc_allocation_size(self.as_ref()) + self.as_ref().try_dynamic_usage()?)
+                        / $rc::strong_count(self),
+                )
+            }
+
+            fn may_overcount(&self) -> bool {
+                // The allocation is divided between the strong pointers, so it is never
+                // counted more than once.
+                self.as_ref().may_overcount()
+            }
+
+            fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+                // Record the allocation before measuring its contents, so that a cycle
+                // back to it terminates.
+                if visited.insert($rc::as_ptr(self)) {
+                    rc_allocation_size(self.as_ref()) + self.as_ref().dedup_dynamic_usage(visited)
+                } else {
+                    0
+                }
+            }
+
+            fn dynamic_usage_used(&self) -> usize {
+                (rc_allocation_size(self.as_ref()) + self.as_ref().dynamic_usage_used())
+                    / $rc::strong_count(self)
+            }
+        }
+    };
+}
+
+impl_rc_dynamic_usage!(Rc);
+#[cfg(target_has_atomic = "ptr")]
+impl_rc_dynamic_usage!(Arc);
+
+impl<T: ?Sized> DynamicUsage for alloc::rc::Weak<T> {
+    // A weak pointer doesn't own its pointee, which is measured through its strong
+    // pointers.
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        0
+    }
+
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T: ?Sized> DynamicUsage for alloc::sync::Weak<T> {
+    // A weak pointer doesn't own its pointee, which is measured through its strong
+    // pointers.
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        0
+    }
+
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
+
+impl<T: Copy + DynamicUsage> DynamicUsage for Cell<T> {
+    fn dynamic_usage(&self) -> usize {
+        self.get().dynamic_usage()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        self.get().dynamic_usage_bounds()
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.get().try_dynamic_usage()
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.get().may_overcount()
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.get().dedup_dynamic_usage(visited)
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        self.get().dynamic_usage_used()
+    }
+}
+
+/// The contents of a `RefCell` can only be measured while they are not mutably borrowed.
+/// While they are, `dynamic_usage` returns 0, `dynamic_usage_bounds` returns `(0, None)`,
+/// and `try_dynamic_usage` returns `None`.
+impl<T: DynamicUsage + ?Sized> DynamicUsage for RefCell<T> {
+    fn dynamic_usage(&self) -> usize {
+        self.try_borrow().map_or(0, |inner| inner.dynamic_usage())
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        self.try_borrow()
+            .map(|inner| inner.dynamic_usage_bounds())
+            .unwrap_or((0, None))
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.try_borrow()
+            .ok()
+            .and_then(|inner| inner.try_dynamic_usage())
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.try_borrow()
+            .map_or(false, |inner| inner.may_overcount())
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.try_borrow()
+            .map_or(0, |inner| inner.dedup_dynamic_usage(visited))
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        self.try_borrow()
+            .map_or(0, |inner| inner.dynamic_usage_used())
+    }
+}
+
+//
+// Collections
+//
+
+#[cfg(not(memuse_allocator_api))]
+impl_iterable_dynamic_usage!(
+    Vec<T>,
+    |c: &Vec<T>| c.capacity() * mem::size_of::<T>(),
+    |c: &Vec<T>| c.len() * mem::size_of::<T>()
+);
+
+// The size of the buffer doesn't depend on the allocator it is allocated with.
+#[cfg(memuse_allocator_api)]
+impl_iterable_dynamic_usage!(
+    [A: core::alloc::Allocator] Vec<T, A>,
+    |c: &Vec<T, A>| c.capacity() * mem::size_of::<T>(),
+    |c: &Vec<T, A>| c.len() * mem::size_of::<T>()
+);
+
+impl<T: DynamicUsage> ContainerUsage for Vec<T> {
+    fn minimal_usage(&self) -> usize {
+        self.len() * mem::size_of::<T>()
+            + self.iter().map(DynamicUsage::dynamic_usage).sum::<usize>()
+    }
+
+    fn utilization(&self) -> f64 {
+        utilization(self.len(), self.capacity())
+    }
+}
+
+impl_iterable_dynamic_usage!(BinaryHeap<T>, |c: &BinaryHeap<T>| {
+    // BinaryHeap<T> is a wrapper around Vec<T>
+    c.capacity() * mem::size_of::<T>()
+});
+
+// Each element of a LinkedList<T> is stored in its own heap-allocated node. The node
+// layout is private, so we mirror it here. It is sourced from here:
+//   https://github.com/rust-lang/rust/blob/1.66.0/library/alloc/src/collections/linked_list.rs
+#[allow(dead_code)]
+pub(crate) struct LinkedListNode<T> {
+    next: Option<core::ptr::NonNull<LinkedListNode<T>>>,
+    prev: Option<core::ptr::NonNull<LinkedListNode<T>>>,
+    element: T,
+}
+
+impl_iterable_dynamic_usage!(LinkedList<T>, |c: &LinkedList<T>| {
+    c.len() * mem::size_of::<LinkedListNode<T>>()
+});
+
+/// The number of slots that a `VecDeque`'s ring buffer always leaves empty.
+///
+/// Before Rust 1.67, the ring buffer left one slot empty, and wasn't included in the
+/// `VecDeque`'s capacity.
+#[cfg(memuse_vecdeque_no_empty_slot)]
+const VECDEQUE_EMPTY_SLOTS: usize = 0;
+#[cfg(not(memuse_vecdeque_no_empty_slot))]
+const VECDEQUE_EMPTY_SLOTS: usize = 1;
+
+impl_iterable_dynamic_usage!(
+    VecDeque<T>,
+    |c: &VecDeque<T>| (c.capacity() + VECDEQUE_EMPTY_SLOTS) * mem::size_of::<T>(),
+    |c: &VecDeque<T>| c.len() * mem::size_of::<T>()
+);
+
+impl<T: DynamicUsage> ContainerUsage for VecDeque<T> {
+    fn minimal_usage(&self) -> usize {
+        (self.len() + VECDEQUE_EMPTY_SLOTS) * mem::size_of::<T>()
+            + self.iter().map(DynamicUsage::dynamic_usage).sum::<usize>()
+    }
+
+    fn utilization(&self) -> f64 {
+        utilization(self.len(), self.capacity())
+    }
+}
+
+mod btree;
+
+pub mod layout;
+
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+mod hash;
+
+//
+// Synchronization primitives
+//
+
+#[cfg(feature = "std")]
+mod sync;
+
+//
+// External crate types (provided for helpfulness, since `DynamicUsage` can only be
+// implemented either here or in the external crate).
+//
+
+#[cfg(feature = "ahash")]
+impl_no_dynamic_usage!(ahash::RandomState);
+
+/// Adapter that implements [`allocative::Allocative`] for any type that implements
+/// [`DynamicUsage`].
+///
+/// `memuse` only knows the total dynamic memory usage of a value, so it is reported to
+/// the [`allocative::Visitor`] as a single unique heap allocation, with no children.
+///
+/// ```
+/// use memuse::AllocativeAdapter;
+///
+/// let buffer = AllocativeAdapter(Vec::<u64>::with_capacity(16));
+/// assert_eq!(allocative::size_of_unique_allocated_data(&buffer), 16 * 8);
+/// ```
+#[cfg(feature = "allocative")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllocativeAdapter<T>(pub T);
+
+#[cfg(feature = "allocative")]
+impl<T: DynamicUsage> allocative::Allocative for AllocativeAdapter<T> {
+    fn visit<'a, 'b: 'a>(&self, visitor: &'a mut allocative::Visitor<'b>) {
+        let mut visitor = visitor.enter_self_sized::<Self>();
+        {
+            // We don't know how the heap allocations are referenced from the value, so
+            // we don't attribute any of the value's inline size to the pointer.
+            let mut visitor = visitor.enter_unique(allocative::Key::new("heap"), 0);
+            visitor.visit_simple(
+                allocative::Key::new("dynamic_usage"),
+                self.0.dynamic_usage(),
+            );
+            visitor.exit();
+        }
+        visitor.exit();
+    }
+}
+
+// ArrayVec<T, CAP> stores its elements inline.
+#[cfg(feature = "arrayvec")]
+impl_iterable_dynamic_usage!([const CAP: usize] arrayvec::ArrayVec<T, CAP>, |_| 0);
+
+#[cfg(feature = "arrayvec")]
+impl<const CAP: usize> DynamicUsage for arrayvec::ArrayString<CAP> {
+    // ArrayString<CAP> stores its contents inline.
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        0
+    }
+
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
+
+#[cfg(feature = "ascii")]
+impl_no_dynamic_usage!(ascii::AsciiStr);
+
+#[cfg(feature = "ascii")]
+impl DynamicUsage for ascii::AsciiString {
+    fn dynamic_usage(&self) -> usize {
+        // AsciiString is a wrapper around Vec<AsciiChar>, and AsciiChar is one byte.
+        self.capacity()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let usage = self.capacity();
+        (usage, Some(usage))
+    }
+}
+
+/// `BitVec<T, O>` stores its bits in a heap-allocated buffer of `T` words.
+#[cfg(feature = "bitvec")]
+impl<T: bitvec::store::BitStore, O: bitvec::order::BitOrder> DynamicUsage
+    for bitvec::vec::BitVec<T, O>
+{
+    fn dynamic_usage(&self) -> usize {
+        // The capacity is measured in bits, and excludes any unused bits at the start of
+        // the first word, so round up to whole words.
+        let word_bits = mem::size_of::<T>() * 8;
+        ((self.capacity() + word_bits - 1) / word_bits) * mem::size_of::<T>()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let usage = self.dynamic_usage();
+        (usage, Some(usage))
+    }
+}
+
+#[cfg(feature = "bitvec")]
+impl<A: bitvec::view::BitViewSized, O: bitvec::order::BitOrder> DynamicUsage
+    for bitvec::array::BitArray<A, O>
+{
+    // BitArray<A, O> stores its bits inline.
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        0
+    }
+
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
+
+/// `Bytes` may point into a buffer that is shared with its clones (or with other `Bytes`
+/// that were split from the same buffer), or into static memory. The capacity of the
+/// underlying buffer isn't exposed, so the length of the viewed bytes is used as a best
+/// estimate, and summing the usage of clones will over-count the shared buffer.
+#[cfg(feature = "bytes")]
+impl DynamicUsage for bytes::Bytes {
+    fn dynamic_usage(&self) -> usize {
+        self.len()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        // The buffer may be static, or shared with other values.
+        (0, None)
+    }
+
+    fn may_overcount(&self) -> bool {
+        !self.is_unique()
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl DynamicUsage for bytes::BytesMut {
+    fn dynamic_usage(&self) -> usize {
+        self.capacity()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let usage = self.capacity();
+        (usage, Some(usage))
+    }
+}
+
+#[cfg(feature = "compact_str")]
+impl DynamicUsage for compact_str::CompactString {
+    fn dynamic_usage(&self) -> usize {
+        // Short strings are stored inline.
+        if !self.is_heap_allocated() {
+            return 0;
+        }
+
+        // The capacity is stored inline in all but the last byte of a usize. Larger
+        // capacities are stored at the start of the heap allocation, padded to the
+        // alignment of a usize. The layout is sourced from here:
+        //   https://github.com/ParkMyCar/compact_str/blob/v0.8.0/compact_str/src/repr/heap.rs
+        const MAX_INLINE_CAPACITY: usize = (1 << ((mem::size_of::<usize>() - 1) * 8)) - 2;
+        let capacity = self.capacity();
+        if capacity <= MAX_INLINE_CAPACITY {
+            capacity
+        } else {
+            Layout::new::<usize>()
+                .extend(Layout::array::<u8>(capacity).expect("already allocated"))
+                .map(|(layout, _)| layout.pad_to_align().size())
+                .expect("already allocated")
+        }
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let usage = self.dynamic_usage();
+        (usage, Some(usage))
+    }
+}
+
+/// Adapter that implements [`deepsize::DeepSizeOf`] for any type that implements
+/// [`DynamicUsage`].
+///
+/// The adapter reports [`DynamicUsage::dynamic_usage`] as the size of the value's
+/// children. It does not use the [`deepsize::Context`] to track shared references, so
+/// allocations shared with other values are counted every time they are reached (see
+/// [`DynamicUsage::may_overcount`]).
+///
+/// ```
+/// use deepsize::DeepSizeOf;
+/// use memuse::DeepSizeAdapter;
+///
+/// let buffer = DeepSizeAdapter(Vec::<u64>::with_capacity(16));
+/// assert_eq!(buffer.deep_size_of(), std::mem::size_of::<Vec<u64>>() + 16 * 8);
+/// ```
+#[cfg(feature = "deepsize")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeepSizeAdapter<T>(pub T);
+
+#[cfg(feature = "deepsize")]
+impl<T: DynamicUsage> deepsize::DeepSizeOf for DeepSizeAdapter<T> {
+    fn deep_size_of_children(&self, _: &mut deepsize::Context) -> usize {
+        self.0.dynamic_usage()
+    }
+}
+
+/// Returns `true` if `s` is stored inline.
+///
+/// `ecow` doesn't expose whether a string is inline, but the contents of an inline string
+/// are stored within the `EcoString` itself.
+#[cfg(feature = "ecow")]
+fn ecow_string_is_inline(s: &ecow::EcoString) -> bool {
+    let start = s as *const ecow::EcoString as usize;
+    let contents = s.as_str().as_ptr() as usize;
+    (start..start + mem::size_of::<ecow::EcoString>()).contains(&contents)
+}
+
+/// `EcoString` stores short strings inline, and longer strings in a reference-counted
+/// heap allocation that may be shared with clones of the string. A string that has been
+/// shortened (or cloned from such a string) stays on the heap, even if it would fit
+/// inline. The estimate counts the length of a heap-allocated string (ignoring any spare
+/// capacity); summing the usage of clones will over-count the shared allocation.
+#[cfg(feature = "ecow")]
+impl DynamicUsage for ecow::EcoString {
+    fn dynamic_usage(&self) -> usize {
+        if ecow_string_is_inline(self) {
+            0
+        } else {
+            self.len()
+        }
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        // The allocation may be shared with other strings.
+        (0, None)
+    }
+
+    fn may_overcount(&self) -> bool {
+        !ecow_string_is_inline(self)
+    }
+}
+
+/// `EcoVec<T>` stores its elements in a reference-counted heap allocation that may be
+/// shared with clones of the vector. Summing the usage of clones will over-count the
+/// shared allocation.
+#[cfg(feature = "ecow")]
+impl<T: DynamicUsage> DynamicUsage for ecow::EcoVec<T> {
+    fn dynamic_usage(&self) -> usize {
+        // The allocation starts with a header containing the reference count and the
+        // capacity.
+        let base = match self.capacity() {
+            0 => 0,
+            capacity => 2 * mem::size_of::<usize>() + capacity * mem::size_of::<T>(),
+        };
+        base + self.iter().map(DynamicUsage::dynamic_usage).sum::<usize>()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        // The allocation may be shared with other vectors.
+        (0, None)
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.capacity() > 0
+    }
+}
+
+#[cfg(feature = "foldhash")]
+impl_no_dynamic_usage!(
+    foldhash::fast::FixedState,
+    foldhash::fast::RandomState,
+    foldhash::fast::SeedableRandomState,
+    foldhash::quality::FixedState,
+    foldhash::quality::RandomState,
+    foldhash::quality::SeedableRandomState
+);
+
+// heapless::Vec<T, N> stores its elements inline.
+#[cfg(feature = "heapless")]
+impl_iterable_dynamic_usage!([const N: usize] heapless::Vec<T, N>, |_| 0);
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> DynamicUsage for heapless::String<N> {
+    // heapless::String<N> stores its contents inline.
+    #[inline(always)]
+    fn dynamic_usage(&self) -> usize {
+        0
+    }
+
+    #[inline(always)]
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+}
+
+/// `heapless::IndexMap<K, V, S, N>` (including `heapless::FnvIndexMap<K, V, N>`) stores
+/// its entries and their hashes inline, so only the heap memory of the keys and values
+/// is counted.
+#[cfg(feature = "heapless")]
+impl<K: DynamicUsage, V: DynamicUsage, S, const N: usize> DynamicUsage
+    for heapless::IndexMap<K, V, S, N>
+{
+    fn dynamic_usage(&self) -> usize {
+        self.iter()
+            .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+            .sum()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        self.iter()
+            .fold((0, Some(0)), |(acc_lower, acc_upper), (k, v)| {
+                let (k_lower, k_upper) = k.dynamic_usage_bounds();
+                let (v_lower, v_upper) = v.dynamic_usage_bounds();
+                (
+                    acc_lower + k_lower + v_lower,
+                    acc_upper
+                        .zip(k_upper)
+                        .zip(v_upper)
+                        .map(|((a, b), c)| a + b + c),
+                )
+            })
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        self.iter().try_fold(0, |acc, (k, v)| {
+            Some(acc + k.try_dynamic_usage()? + v.try_dynamic_usage()?)
+        })
+    }
+
+    fn may_overcount(&self) -> bool {
+        self.iter()
+            .any(|(k, v)| k.may_overcount() || v.may_overcount())
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        self.iter()
+            .map(|(k, v)| k.dedup_dynamic_usage(visited) + v.dedup_dynamic_usage(visited))
+            .sum()
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        self.iter()
+            .map(|(k, v)| k.dynamic_usage_used() + v.dynamic_usage_used())
+            .sum()
+    }
+}
+
+/// Returns the number of branch nodes in a tree with the given number of leaf nodes, if
+/// every branch node is full.
+#[cfg(feature = "im")]
+fn im_branch_nodes(leaves: usize, fan_out: usize) -> usize {
+    let mut branches = 0;
+    let mut nodes = leaves;
+    while nodes > 1 {
+        nodes = (nodes + fan_out - 1) / fan_out;
+        branches += nodes;
+    }
+    branches
+}
+
+/// Returns a lower bound on, and an estimate of, the size of the nodes of `v`.
+///
+/// `im::Vector` stores its elements in chunks of 64, which are the leaves of an RRB
+/// tree with a fan-out of 64.
+#[cfg(feature = "im")]
+fn im_vector_nodes_usage<A: Clone>(v: &im::Vector<A>) -> (usize, usize) {
+    const CHUNK_SIZE: usize = 64;
+
+    // Small vectors are stored inline.
+    if v.is_inline() {
+        return (0, 0);
+    }
+
+    let leaves = (v.len() + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    let lower = leaves * CHUNK_SIZE * mem::size_of::<A>();
+    // Each branch node holds (at least) a pointer to each of its children.
+    let branches = im_branch_nodes(leaves, CHUNK_SIZE) * CHUNK_SIZE * mem::size_of::<usize>();
+    (lower, lower + branches)
+}
+
+/// Returns a lower bound on, and an estimate of, the size of the nodes of `m`.
+///
+/// `im::HashMap` is a hash array mapped trie, whose nodes each have 32 slots for entries
+/// (alongside their 32-bit hashes) or pointers to child nodes.
+#[cfg(feature = "im")]
+fn im_hashmap_nodes_usage<K, V, S>(m: &im::HashMap<K, V, S>) -> (usize, usize) {
+    const HASH_WIDTH: usize = 32;
+
+    let node = HASH_WIDTH * mem::size_of::<((K, V), u32)>() + mem::size_of::<u32>();
+    // The root node is allocated even when the map is empty.
+    let leaves = ((m.len() + HASH_WIDTH - 1) / HASH_WIDTH).max(1);
+    let lower = leaves * node;
+    (lower, lower + im_branch_nodes(leaves, HASH_WIDTH) * node)
+}
+
+/// **This is a per-instance estimate.** `im::Vector` is a persistent data structure:
+/// clones of a vector, and vectors derived from it, share most of their nodes. This
+/// sharing is not deduplicated, so summing the usage of several versions of a vector
+/// (for example, the states in an undo history) over-counts the shared nodes.
+///
+/// The tree isn't exposed, so the size of its nodes is estimated from the vector's length,
+/// as if every node were full. The upper bound is `None`.
+#[cfg(feature = "im")]
+impl<A: DynamicUsage + Clone> DynamicUsage for im::Vector<A> {
+    fn dynamic_usage(&self) -> usize {
+        im_vector_nodes_usage(self).1 + self.iter().map(DynamicUsage::dynamic_usage).sum::<usize>()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let lower = im_vector_nodes_usage(self).0
+            + self
+                .iter()
+                .map(|a| a.dynamic_usage_bounds().0)
+                .sum::<usize>();
+        (lower, None)
+    }
+
+    fn may_overcount(&self) -> bool {
+        // Any heap-allocated node may be shared with another vector.
+        !self.is_inline() || self.iter().any(DynamicUsage::may_overcount)
+    }
+}
+
+/// **This is a per-instance estimate.** `im::HashMap` is a persistent data structure:
+/// clones of a map, and maps derived from it, share most of their nodes. This sharing is
+/// not deduplicated, so summing the usage of several versions of a map (for example, the
+/// states in an undo history) over-counts the shared nodes.
+///
+/// The trie isn't exposed, so the size of its nodes is estimated from the map's length,
+/// as if every node were full. The upper bound is `None`.
+#[cfg(feature = "im")]
+impl<K: DynamicUsage, V: DynamicUsage, S: DynamicUsage> DynamicUsage for im::HashMap<K, V, S> {
+    fn dynamic_usage(&self) -> usize {
+        // The hasher is stored in an `Arc` that is shared with clones of the map.
+        im_hashmap_nodes_usage(self).1
+            + self.hasher().dynamic_usage()
+            + self
+                .iter()
+                .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+                .sum::<usize>()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let lower = im_hashmap_nodes_usage(self).0
+            + self.hasher().dynamic_usage_bounds().0
+            + self
+                .iter()
+                .map(|(k, v)| k.dynamic_usage_bounds().0 + v.dynamic_usage_bounds().0)
+                .sum::<usize>();
+        (lower, None)
+    }
+
+    fn may_overcount(&self) -> bool {
+        // Every map has a heap-allocated root node, which may be shared with another map.
+        true
+    }
+}
+
+#[cfg(feature = "nonempty")]
+impl_iterable_dynamic_usage!(nonempty::NonEmpty<T>, |c: &nonempty::NonEmpty<T>| {
+    // NonEmpty<T> stores its head element separately from its tail Vec<T>.
+    (c.capacity() - 1) * mem::size_of::<T>()
+});
+
+/// Returns the size of the digits of `n`.
+///
+/// A `BigUint` stores its magnitude as a `Vec` of digits, which are `u64` on 64-bit
+/// targets and `u32` otherwise. The `Vec`'s capacity isn't exposed, so this is a lower
+/// bound on its dynamic memory usage.
+#[cfg(feature = "num-bigint")]
+fn biguint_digits_size(n: &num_bigint::BigUint) -> usize {
+    #[cfg(target_pointer_width = "64")]
+    {
+        n.iter_u64_digits().len() * mem::size_of::<u64>()
+    }
+    #[cfg(not(target_pointer_width = "64"))]
+    {
+        n.iter_u32_digits().len() * mem::size_of::<u32>()
+    }
+}
+
+/// The digit `Vec`'s capacity isn't exposed, so only its length is counted, and the
+/// upper bound is `None`.
+#[cfg(feature = "num-bigint")]
+impl DynamicUsage for num_bigint::BigUint {
+    fn dynamic_usage(&self) -> usize {
+        biguint_digits_size(self)
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (biguint_digits_size(self), None)
+    }
+}
+
+/// The digit `Vec`'s capacity isn't exposed, so only its length is counted, and the
+/// upper bound is `None`.
+#[cfg(feature = "num-bigint")]
+impl DynamicUsage for num_bigint::BigInt {
+    fn dynamic_usage(&self) -> usize {
+        self.magnitude().dynamic_usage()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        self.magnitude().dynamic_usage_bounds()
+    }
+}
+
+/// The estimated number of bytes used by a compiled `regex::Regex` per byte of its
+/// pattern.
+///
+/// The estimate has no fixed base term, so it under-counts short patterns. It also
+/// excludes the caches that a `Regex` grows during matching.
+#[cfg(feature = "regex")]
+const REGEX_USAGE_PER_PATTERN_BYTE: usize = 256;
+
+/// The compiled program and the caches of a `Regex` aren't exposed, so its dynamic memory
+/// usage is a heuristic: the length of its pattern multiplied by 256. This may be far from
+/// its real size in either direction. The lower bound is the length of the pattern (which the
+/// `Regex` stores), and the upper bound is `None`.
+#[cfg(feature = "regex")]
+impl DynamicUsage for regex::Regex {
+    fn dynamic_usage(&self) -> usize {
+        self.as_str().len() * REGEX_USAGE_PER_PATTERN_BYTE
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (self.as_str().len(), None)
+    }
+}
+
+#[cfg(feature = "rustc-hash")]
+impl_no_dynamic_usage!(rustc_hash::FxBuildHasher, rustc_hash::FxSeededState);
+
+/// Returns the lower and upper bounds on the memory allocated for the storage of a
+/// `serde_json::Map` containing `entries` entries.
+#[cfg(feature = "serde_json")]
+fn serde_json_map_usage_bounds(entries: usize) -> (usize, Option<usize>) {
+    // serde_json::Map is backed by a BTreeMap, unless serde_json's preserve_order feature
+    // flag is enabled, in which case it is backed by an IndexMap. The IndexMap's capacity
+    // isn't exposed, so we only have a lower bound.
+    #[cfg(not(feature = "serde_json_preserve_order"))]
+    {
+        let (lower, upper) =
+            btree::btree_dynamic_usage_bounds::<String, serde_json::Value>(entries);
+        (lower, Some(upper))
+    }
+    #[cfg(feature = "serde_json_preserve_order")]
+    {
+        (
+            hash::indexmap_usage_for_capacity::<String, serde_json::Value>(entries),
+            None,
+        )
+    }
+}
+
+/// If serde_json's `preserve_order` feature flag is enabled, the `serde_json_preserve_order`
+/// feature flag must also be enabled for the map's storage to be measured correctly.
+#[cfg(feature = "serde_json")]
+impl DynamicUsage for serde_json::Map<String, serde_json::Value> {
+    fn dynamic_usage(&self) -> usize {
+        let storage = match serde_json_map_usage_bounds(self.len()) {
+            // Use the midpoint of the bounds as our estimate.
+            (lower, Some(upper)) => lower + (upper - lower) / 2,
+            (lower, None) => lower,
+        };
+        storage
+            + self
+                .iter()
+                .map(|(k, v)| k.dynamic_usage() + v.dynamic_usage())
+                .sum::<usize>()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = serde_json_map_usage_bounds(self.len());
+        let (inner_lower, inner_upper) = self
+            .iter()
+            .map(|(k, v)| {
+                let (k_lower, k_upper) = k.dynamic_usage_bounds();
+                let (v_lower, v_upper) = v.dynamic_usage_bounds();
+                (k_lower + v_lower, k_upper.zip(v_upper).map(|(k, v)| k + v))
+            })
+            .fold((0, Some(0)), |(acc_lower, acc_upper), (lower, upper)| {
+                (acc_lower + lower, acc_upper.zip(upper).map(|(a, b)| a + b))
+            });
+        (
+            lower + inner_lower,
+            upper.zip(inner_upper).map(|(a, b)| a + b),
+        )
+    }
+}
+
+/// Numbers are measured as not allocating, even if serde_json's `arbitrary_precision`
+/// feature flag is enabled.
+#[cfg(feature = "serde_json")]
+impl DynamicUsage for serde_json::Value {
+    fn dynamic_usage(&self) -> usize {
+        match self {
+            serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::Number(_) => {
+                0
+            }
+            serde_json::Value::String(s) => s.dynamic_usage(),
+            serde_json::Value::Array(a) => a.dynamic_usage(),
+            serde_json::Value::Object(m) => m.dynamic_usage(),
+        }
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        match self {
+            serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::Number(_) => {
+                (0, Some(0))
+            }
+            serde_json::Value::String(s) => s.dynamic_usage_bounds(),
+            serde_json::Value::Array(a) => a.dynamic_usage_bounds(),
+            serde_json::Value::Object(m) => m.dynamic_usage_bounds(),
+        }
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl_iterable_dynamic_usage!(
+    [const N: usize] smallvec::SmallVec<[T; N]>,
+    |c: &smallvec::SmallVec<[T; N]>| {
+        // SmallVec only allocates once it spills its inline storage.
+        if c.spilled() {
+            c.capacity() * mem::size_of::<T>()
+        } else {
+            0
+        }
+    }
+);
+
+// tinyvec's ArrayVec<A> stores its elements inline.
+#[cfg(feature = "tinyvec")]
+impl_iterable_dynamic_usage!([A: tinyvec::Array<Item = T>] tinyvec::ArrayVec<A>, |_| 0);
+
+#[cfg(feature = "tinyvec")]
+impl_iterable_dynamic_usage!(
+    [A: tinyvec::Array<Item = T>] tinyvec::TinyVec<A>,
+    |c: &tinyvec::TinyVec<A>| match c {
+        tinyvec::TinyVec::Inline(_) => 0,
+        tinyvec::TinyVec::Heap(v) => v.capacity() * mem::size_of::<T>(),
+    }
+);
+
+/// `Url` stores its serialization in a `String`, alongside the inline offsets of its
+/// components. The capacity of the `String` isn't exposed, so the length of the
+/// serialization is used as a best estimate, and the upper bound is `None`.
+#[cfg(feature = "url")]
+impl DynamicUsage for url::Url {
+    fn dynamic_usage(&self) -> usize {
+        self.as_str().len()
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        (self.as_str().len(), None)
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl_no_dynamic_usage!(uuid::Uuid);
+
+/// Implements `DynamicUsage` for a `weak_table::WeakValueHashMap` with the given weak
+/// pointer type.
+#[cfg(feature = "weak-table")]
+macro_rules! impl_weak_value_hash_map_dynamic_usage {
+    ($weak:ident, $strong:ident) => {
+        /// The table stores a key, a weak pointer, and a hash code in every bucket. Only
+        /// the live entries are measured beyond their bucket: each entry's key, and the
+        /// shared allocation of its value. A value that is stored under several keys is
+        /// counted once.
+        ///
+        /// Measuring an entry briefly upgrades its weak pointer; the strong pointer is
+        /// dropped before the next entry is visited, so no value is kept alive by the
+        /// measurement.
+        impl<K, V, S> DynamicUsage for weak_table::WeakValueHashMap<K, alloc::$weak::Weak<V>, S>
+        where
+            K: DynamicUsage + Eq + core::hash::Hash,
+            V: DynamicUsage + ?Sized,
+            S: DynamicUsage + core::hash::BuildHasher,
+        {
+            fn dynamic_usage(&self) -> usize {
+                let mut seen = alloc::collections::BTreeSet::new();
+                let entries = self
+                    .iter()
+                    .map(|(k, v)| {
+                        k.dynamic_usage()
+                            + if seen.insert(alloc::$weak::$strong::as_ptr(&v) as *const ()) {
+                                rc_allocation_size(&*v) + (*v).dynamic_usage()
+                            } else {
+                                0
+                            }
+                    })
+                    .sum::<usize>();
+                weak_value_hash_map_table_usage::<K, alloc::$weak::Weak<V>>(self.capacity())
+                    + self.hasher().dynamic_usage()
+                    + entries
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+                // Values can be dropped by their other owners at any time, so only the
+                // table itself is certain.
+                (
+                    weak_value_hash_map_table_usage::<K, alloc::$weak::Weak<V>>(self.capacity())
+                        + self.hasher().dynamic_usage_bounds().0,
+                    None,
+                )
+            }
+
+            fn may_overcount(&self) -> bool {
+                // The live values are also owned outside the map.
+                self.iter().next().is_some()
+            }
+        }
+    };
+}
+
+/// Returns the size of the bucket array of a `weak_table::WeakValueHashMap`.
+#[cfg(feature = "weak-table")]
+fn weak_value_hash_map_table_usage<K, W>(capacity: usize) -> usize {
+    // Each bucket is an `Option<(K, W, HashCode)>`, where `HashCode` wraps a `u64`.
+    capacity * mem::size_of::<Option<(K, W, u64)>>()
+}
+
+#[cfg(feature = "weak-table")]
+impl_weak_value_hash_map_dynamic_usage!(rc, Rc);
+#[cfg(feature = "weak-table")]
+impl_weak_value_hash_map_dynamic_usage!(sync, Arc);
+
+//
+// Larger definitions (placed at the end so they render more nicely in docs).
+//
+
+mod tuple;
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    /// A value that reports that it may over-count, for checking that `may_overcount` is
+    /// forwarded through wrappers.
+    struct Overcounting;
+
+    impl DynamicUsage for Overcounting {
+        fn dynamic_usage(&self) -> usize {
+            0
+        }
+
+        fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+            (0, Some(0))
+        }
+
+        fn may_overcount(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn standard_types() {
+        assert_eq!(129u8.dynamic_usage(), 0);
+        assert_eq!(3i128.dynamic_usage(), 0);
+        assert_eq!(7.0f32.dynamic_usage(), 0);
+        assert_eq!("foobar".dynamic_usage(), 0);
+
+        assert_eq!(129u8.dynamic_usage_bounds(), (0, Some(0)));
+        assert_eq!(3i128.dynamic_usage_bounds(), (0, Some(0)));
+        assert_eq!(7.0f32.dynamic_usage_bounds(), (0, Some(0)));
+        assert_eq!("foobar".dynamic_usage_bounds(), (0, Some(0)));
+    }
+
+    #[cfg(target_has_atomic = "64")]
+    #[test]
+    fn atomics() {
+        let a = atomic::AtomicU64::new(7);
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let mut inner = 42;
+        let b = atomic::AtomicPtr::new(&mut inner);
+        assert_eq!(b.dynamic_usage(), 0);
+        assert_eq!(b.dynamic_usage_bounds(), (0, Some(0)));
+    }
+
+    #[test]
+    fn human_usage() {
+        assert_eq!(HumanUsage(0).to_string(), "0 B");
+        assert_eq!(HumanUsage(1).to_string(), "1 B");
+        assert_eq!(HumanUsage(1023).to_string(), "1023 B");
+        assert_eq!(HumanUsage(1024).to_string(), "1.00 KiB");
+        assert_eq!(HumanUsage(1536).to_string(), "1.50 KiB");
+        assert_eq!(HumanUsage(1024 * 1024).to_string(), "1.00 MiB");
+        assert_eq!(HumanUsage(3 * 1024 * 1024 * 1024).to_string(), "3.00 GiB");
+
+        // Values that round up to a unit boundary are displayed in the larger unit.
+        assert_eq!(HumanUsage(1024 * 1024 - 6).to_string(), "1023.99 KiB");
+        assert_eq!(HumanUsage(1024 * 1024 - 1).to_string(), "1.00 MiB");
+        assert_eq!(HumanUsage(1024 * 1024 * 1024 - 1).to_string(), "1.00 GiB");
+
+        assert_eq!(vec![0u32; 256].human_usage(), HumanUsage(1024));
+    }
+
+    #[test]
+    fn string() {
+        assert_eq!(String::new().dynamic_usage(), 0);
+        assert_eq!("foobar".to_string().dynamic_usage(), 6);
+
+        assert_eq!(String::new().dynamic_usage_bounds(), (0, Some(0)));
+        assert_eq!("foobar".to_string().dynamic_usage_bounds(), (6, Some(6)));
+    }
+
+    #[test]
+    fn boxed() {
+        let a: u64 = 7;
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let b: Box<u64> = Box::new(42);
+        assert_eq!(b.dynamic_usage(), 8);
+        assert_eq!(b.dynamic_usage_bounds(), (8, Some(8)));
+
+        let capacity = 7;
+        let c: Box<Vec<u16>> = Box::new(Vec::with_capacity(capacity));
+        let expected = mem::size_of::<Vec<u16>>() + capacity * mem::size_of::<u16>();
+        assert_eq!(c.dynamic_usage(), expected);
+        assert_eq!(c.dynamic_usage_bounds(), (expected, Some(expected)));
+    }
+
+    #[test]
+    fn boxed_trait_objects() {
+        trait Shape: DynamicUsage {}
+        impl Shape for Vec<u64> {}
+        impl Shape for (u8, String) {}
+
+        let a: Box<dyn Shape> = Box::new(vec![7u64; 4]);
+        let expected_a = mem::size_of::<Vec<u64>>() + 32;
+        assert_eq!(a.dynamic_usage(), expected_a);
+        assert_eq!(a.dynamic_usage_bounds(), (expected_a, Some(expected_a)));
+
+        let b: Box<dyn Shape> = Box::new((1u8, String::with_capacity(10)));
+        let expected_b = mem::size_of::<(u8, String)>() + 10;
+        assert_eq!(b.dynamic_usage(), expected_b);
+
+        let shapes = vec![a, b];
+        assert_eq!(
+            shapes.dynamic_usage(),
+            2 * mem::size_of::<Box<dyn Shape>>() + expected_a + expected_b,
+        );
+
+        let c: Box<dyn DynamicUsage> = Box::new(String::with_capacity(5));
+        assert_eq!(c.dynamic_usage(), mem::size_of::<String>() + 5);
+    }
+
+    #[test]
+    fn boxed_slice() {
+        let a: Box<[u64]> = Vec::new().into_boxed_slice();
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let b: Box<[u64]> = vec![7; 5].into_boxed_slice();
+        assert_eq!(b.dynamic_usage(), 5 * 8);
+        assert_eq!(b.dynamic_usage_bounds(), (5 * 8, Some(5 * 8)));
+
+        // A boxed slice has no spare capacity, even if the Vec it was made from did.
+        let mut v = Vec::with_capacity(10);
+        v.push(String::with_capacity(3));
+        v.push(String::from("foobar"));
+        let c = v.into_boxed_slice();
+        let expected = 2 * mem::size_of::<String>() + 3 + 6;
+        assert_eq!(c.dynamic_usage(), expected);
+        assert_eq!(c.dynamic_usage_bounds(), (expected, Some(expected)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn ffi_strings() {
+        use std::ffi::{CStr, CString, OsStr, OsString};
+
+        let a = OsStr::new("borrowed");
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let mut b = OsString::with_capacity(20);
+        b.push("owned");
+        assert_eq!(b.dynamic_usage(), b.capacity());
+        assert_eq!(b.dynamic_usage_bounds(), (b.capacity(), Some(b.capacity())));
+        assert!(b.dynamic_usage() >= 20);
+
+        let c = CString::new("owned").unwrap();
+        assert_eq!(c.dynamic_usage(), 6);
+        assert_eq!(c.dynamic_usage_bounds(), (6, Some(6)));
+
+        let d: &CStr = c.as_c_str();
+        assert_eq!(d.dynamic_usage(), 0);
+        assert_eq!(d.dynamic_usage_bounds(), (0, Some(0)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn path() {
+        use std::path::{Path, PathBuf};
+
+        let a = Path::new("/usr/local/bin");
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let mut b = PathBuf::with_capacity(32);
+        b.push("/usr/local");
+        b.push("bin");
+        assert_eq!(b.dynamic_usage(), b.capacity());
+        assert_eq!(b.dynamic_usage_bounds(), (b.capacity(), Some(b.capacity())));
+        assert!(b.dynamic_usage() >= 32);
+
+        // Borrowing a PathBuf as a Path doesn't own the allocation.
+        assert_eq!(b.as_path().dynamic_usage(), 0);
+    }
+
+    #[test]
+    fn boxed_str() {
+        let a: Box<str> = String::new().into_boxed_str();
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        // The usage is the length in bytes, not in chars.
+        let b: Box<str> = Box::from("añ€😀");
+        assert_eq!(b.chars().count(), 4);
+        assert_eq!(b.dynamic_usage(), 1 + 2 + 3 + 4);
+        assert_eq!(b.dynamic_usage_bounds(), (10, Some(10)));
+
+        // A boxed str has no spare capacity.
+        let mut c = String::with_capacity(100);
+        c.push_str("foobar");
+        assert_eq!(c.into_boxed_str().dynamic_usage(), 6);
+    }
+
+    #[test]
+    fn rc() {
+        let header = 2 * mem::size_of::<usize>();
+        let total = header + mem::size_of::<Vec<u8>>() + 30;
+
+        let a = Rc::new(vec![0u8; 30]);
+        assert_eq!(a.dynamic_usage(), total);
+        assert_eq!(a.dynamic_usage_bounds(), (total, Some(total)));
+        assert!(!a.may_overcount());
+
+        // The allocation is shared between the clones.
+        let b = a.clone();
+        assert_eq!(a.dynamic_usage(), total / 2);
+        assert_eq!(a.dynamic_usage() + b.dynamic_usage(), total);
+        assert_eq!(a.dynamic_usage_bounds(), (total / 2, Some(total)));
+
+        // The clones divide the allocation between them, rather than each counting it.
+        assert!(!a.may_overcount());
+        assert!(!b.may_overcount());
+        assert!(Rc::new(Overcounting).may_overcount());
+
+        let c = b.clone();
+        assert_eq!(a.dynamic_usage(), total / 3);
+        assert!(a.dynamic_usage() + b.dynamic_usage() + c.dynamic_usage() <= total);
+
+        drop(b);
+        drop(c);
+        assert_eq!(a.dynamic_usage(), total);
+    }
+
+    #[cfg(target_has_atomic = "ptr")]
+    #[test]
+    fn arc() {
+        let header = 2 * mem::size_of::<usize>();
+        let total = header + mem::size_of::<String>() + 10;
+
+        let a = Arc::new(String::with_capacity(10));
+        assert_eq!(a.dynamic_usage(), total);
+
+        let b = a.clone();
+        assert_eq!(b.dynamic_usage(), total / 2);
+
+        let c = a.clone();
+        assert_eq!(c.dynamic_usage(), total / 3);
+        assert_eq!(c.dynamic_usage_bounds(), (total / 3, Some(total)));
+
+        // Over-aligned values are padded after the header.
+        #[repr(align(64))]
+        struct Aligned(#[allow(dead_code)] u8);
+        impl_no_dynamic_usage!(Aligned);
+        assert_eq!(Arc::new(Aligned(0)).dynamic_usage(), 128);
+    }
+
+    #[test]
+    fn rc_str() {
+        // The string's bytes follow the reference counts, and the allocation is padded
+        // to the alignment of the counts.
+        let header = 2 * mem::size_of::<usize>();
+        let total = header + 16;
+
+        let a: Rc<str> = Rc::from("hello, world");
+        assert_eq!(a.dynamic_usage(), total);
+        assert_eq!(a.dynamic_usage_bounds(), (total, Some(total)));
+
+        let b = a.clone();
+        assert_eq!(b.dynamic_usage(), total / 2);
+        assert_eq!(b.dynamic_usage_bounds(), (total / 2, Some(total)));
+
+        #[cfg(target_has_atomic = "ptr")]
+        {
+            let c: Arc<str> = Arc::from("hello, world");
+            assert_eq!(c.dynamic_usage(), total);
+            assert_eq!(c.dynamic_usage_bounds(), (total, Some(total)));
+
+            // Empty strings still allocate the reference counts.
+            let d: Arc<str> = Arc::from("");
+            assert_eq!(d.dynamic_usage(), header);
+        }
+    }
+
+    #[test]
+    fn dedup_rc() {
+        let shared = Rc::new(vec![0u8; 100]);
+        let shared_size = rc_allocation_size(&*shared) + 100;
+        let a = vec![shared.clone(), shared.clone(), shared];
+
+        // The shared allocation is counted in full, once.
+        let mut visited = Visited::new();
+        assert!(visited.is_empty());
+        assert_eq!(
+            a.dedup_dynamic_usage(&mut visited),
+            3 * mem::size_of::<Rc<Vec<u8>>>() + shared_size,
+        );
+        assert_eq!(visited.len(), 1);
+
+        // Measuring it again with the same visited set skips it.
+        assert_eq!(a[0].dedup_dynamic_usage(&mut visited), 0);
+
+        // Values that don't share allocations are measured as usual.
+        let b = (Some(vec![0u8; 10]), String::with_capacity(5));
+        assert_eq!(b.dedup_dynamic_usage(&mut visited), b.dynamic_usage());
+    }
+
+    #[cfg(target_has_atomic = "ptr")]
+    #[test]
+    fn dedup_arc() {
+        let shared = Arc::new(String::with_capacity(20));
+        let a: [Option<Arc<String>>; 3] = [Some(shared.clone()), None, Some(shared.clone())];
+
+        let mut visited = Visited::new();
+        assert_eq!(
+            a.dedup_dynamic_usage(&mut visited),
+            rc_allocation_size(&*shared) + 20,
+        );
+    }
+
+    #[test]
+    fn dedup_rc_cycle() {
+        struct Node {
+            data: Vec<u8>,
+            next: RefCell<Option<Rc<Node>>>,
+        }
+
+        impl DynamicUsage for Node {
+            fn dynamic_usage(&self) -> usize {
+                // This never terminates for a cycle of nodes.
+                self.data.dynamic_usage() + self.next.dynamic_usage()
+            }
+
+            fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+                let usage = self.dynamic_usage();
+                (usage, Some(usage))
+            }
+
+            fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+                self.data.dedup_dynamic_usage(visited) + self.next.dedup_dynamic_usage(visited)
+            }
+        }
+
+        let a = Rc::new(Node {
+            data: vec![0; 10],
+            next: RefCell::new(None),
+        });
+        let b = Rc::new(Node {
+            data: vec![0; 20],
+            next: RefCell::new(Some(a.clone())),
+        });
+        *a.next.borrow_mut() = Some(b.clone());
+
+        let node_size = rc_allocation_size(&*a);
+        let mut visited = Visited::new();
+        assert_eq!(a.dedup_dynamic_usage(&mut visited), 2 * node_size + 30);
+        assert_eq!(b.dedup_dynamic_usage(&mut visited), 0);
+
+        // Break the cycle so the nodes are freed.
+        a.next.borrow_mut().take();
+    }
+
+    #[test]
+    fn cow() {
+        let a: Cow<'static, str> = Cow::Borrowed("a static string");
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let mut owned = String::with_capacity(20);
+        owned.push_str("an owned string");
+        let b: Cow<'static, str> = Cow::Owned(owned);
+        assert_eq!(b.dynamic_usage(), 20);
+        assert_eq!(b.dynamic_usage_bounds(), (20, Some(20)));
+
+        // Taking ownership of a borrowed value allocates.
+        let mut c = a.clone();
+        c.to_mut().push('!');
+        assert!(c.dynamic_usage() >= 16);
+        assert_eq!(a.dynamic_usage(), 0);
+    }
+
+    #[test]
+    fn option() {
+        let a: Option<Vec<u8>> = None;
+        let b: Option<Vec<u8>> = Some(vec![7u8; 4]);
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+        assert_eq!(b.dynamic_usage(), 4);
+        assert_eq!(b.dynamic_usage_bounds(), (4, Some(4)));
+    }
+
+    #[test]
+    fn result() {
+        let a: Result<String, Vec<u8>> = Ok(String::with_capacity(10));
+        let b: Result<String, Vec<u8>> = Err(vec![7u8; 4]);
+        assert_eq!(a.dynamic_usage(), 10);
+        assert_eq!(a.dynamic_usage_bounds(), (10, Some(10)));
+        assert_eq!(b.dynamic_usage(), 4);
+        assert_eq!(b.dynamic_usage_bounds(), (4, Some(4)));
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(core::time::Duration::from_secs(1).dynamic_usage(), 0);
+
+        #[cfg(feature = "std")]
+        {
+            let a = std::time::Instant::now();
+            assert_eq!(a.dynamic_usage(), 0);
+            assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+            let b = std::time::SystemTime::now();
+            assert_eq!(b.dynamic_usage(), 0);
+            assert_eq!(b.dynamic_usage_bounds(), (0, Some(0)));
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn net() {
+        use std::collections::HashMap;
+        use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+        let a: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+        assert_eq!(IpAddr::from(Ipv6Addr::LOCALHOST).dynamic_usage(), 0);
+
+        // Only the values of a map keyed by address allocate.
+        let mut connections: HashMap<SocketAddr, Vec<u8>> = HashMap::new();
+        connections.insert(a, vec![0; 10]);
+        let empty: HashMap<SocketAddr, Vec<u8>> = HashMap::with_capacity(connections.capacity());
+        assert_eq!(connections.dynamic_usage(), empty.dynamic_usage() + 10);
+    }
+
+    #[test]
+    fn markers() {
+        use core::marker::{PhantomData, PhantomPinned};
+
+        assert_eq!(().dynamic_usage(), 0);
+        assert_eq!(PhantomPinned.dynamic_usage(), 0);
+        assert_eq!(PhantomPinned.dynamic_usage_bounds(), (0, Some(0)));
+
+        // PhantomData doesn't own a T, even if T allocates.
+        let a: PhantomData<Vec<String>> = PhantomData;
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+        let b: PhantomData<dyn DynamicUsage> = PhantomData;
+        assert_eq!(b.dynamic_usage(), 0);
+    }
+
+    #[test]
+    fn nonzero() {
+        use core::num::{NonZeroI128, NonZeroU32, NonZeroUsize};
+
+        let a = NonZeroU32::new(7).unwrap();
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+        assert_eq!(NonZeroUsize::new(1).unwrap().dynamic_usage(), 0);
+        assert_eq!(NonZeroI128::new(-1).unwrap().dynamic_usage(), 0);
+
+        let b: Vec<Option<NonZeroU32>> = vec![NonZeroU32::new(1), None];
+        assert_eq!(b.dynamic_usage(), 2 * 4);
+    }
+
+    #[test]
+    fn wrapping() {
+        use core::num::Wrapping;
+
+        let a = Wrapping(5u64);
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let b = Wrapping(Vec::<u8>::with_capacity(10));
+        assert_eq!(b.dynamic_usage(), 10);
+        assert_eq!(b.dynamic_usage_used(), 0);
+
+        // A locked inner value is skipped rather than blocking.
+        #[cfg(feature = "std")]
+        {
+            let c = Wrapping(std::sync::Mutex::new(Vec::<u8>::with_capacity(10)));
+            assert_eq!(c.try_dynamic_usage(), Some(10));
+            let _guard = c.0.lock().unwrap();
+            assert_eq!(c.try_dynamic_usage(), None);
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!((0u64..10).dynamic_usage(), 0);
+        assert_eq!((0u64..=10).dynamic_usage_bounds(), (0, Some(0)));
+        assert_eq!((0u64..).dynamic_usage(), 0);
+        assert_eq!((..10u64).dynamic_usage(), 0);
+
+        let a = String::with_capacity(3)..String::with_capacity(5);
+        assert_eq!(a.dynamic_usage(), 8);
+        assert_eq!(a.dynamic_usage_bounds(), (8, Some(8)));
+
+        let b = String::with_capacity(3)..=String::with_capacity(5);
+        assert_eq!(b.dynamic_usage(), 8);
+        assert_eq!(b.dynamic_usage_bounds(), (8, Some(8)));
+
+        assert_eq!((String::with_capacity(3)..).dynamic_usage(), 3);
+        assert_eq!((..String::with_capacity(5)).dynamic_usage(), 5);
+
+        // The other methods are forwarded to the endpoints.
+        let c = Vec::<u8>::with_capacity(3)..Vec::with_capacity(5);
+        assert_eq!(c.try_dynamic_usage(), Some(8));
+        assert_eq!(c.dynamic_usage_used(), 0);
+        assert_eq!((vec![0u8; 2]..=vec![0u8; 3]).dynamic_usage_used(), 5);
+        assert_eq!((Vec::<u8>::with_capacity(3)..).dynamic_usage_used(), 0);
+        assert_eq!((..Vec::<u8>::with_capacity(3)).dynamic_usage_used(), 0);
+
+        let shared = Rc::new(vec![0u8; 100]);
+        let single = shared.dedup_dynamic_usage(&mut Visited::new());
+        let d = shared.clone()..shared;
+        assert!((Overcounting..Overcounting).may_overcount());
+        assert_eq!(d.dedup_dynamic_usage(&mut Visited::new()), single);
+    }
+
+    #[test]
+    fn reverse() {
+        use core::cmp::Reverse;
+
+        let mut heap = BinaryHeap::with_capacity(4);
+        heap.push(Reverse(String::with_capacity(3)));
+        heap.push(Reverse(String::with_capacity(5)));
+        let expected = 4 * mem::size_of::<Reverse<String>>() + 3 + 5;
+        assert_eq!(heap.dynamic_usage(), expected);
+        assert_eq!(heap.dynamic_usage_bounds(), (expected, Some(expected)));
+        assert_eq!(Reverse(String::with_capacity(3)).dynamic_usage_used(), 0);
+
+        // Shared values are counted once.
+        let shared = Rc::new(vec![0u8; 100]);
+        let single = shared.dedup_dynamic_usage(&mut Visited::new());
+        let a = [Reverse(shared.clone()), Reverse(shared)];
+        assert!(Reverse(Overcounting).may_overcount());
+        let mut visited = Visited::new();
+        assert_eq!(a.dedup_dynamic_usage(&mut visited), single);
+        assert_eq!(visited.len(), 1);
+    }
+
+    #[test]
+    fn bound() {
+        use core::ops::Bound;
+
+        assert_eq!(Bound::<String>::Unbounded.dynamic_usage(), 0);
+        assert_eq!(
+            Bound::<String>::Unbounded.dynamic_usage_bounds(),
+            (0, Some(0))
+        );
+
+        let a = Bound::Included(String::with_capacity(3));
+        assert_eq!(a.dynamic_usage(), 3);
+        assert_eq!(a.dynamic_usage_bounds(), (3, Some(3)));
+
+        let b = Bound::Excluded(String::with_capacity(5));
+        assert_eq!(b.dynamic_usage(), 5);
+        assert_eq!(b.dynamic_usage_bounds(), (5, Some(5)));
+
+        // The other methods are forwarded to the endpoint.
+        assert_eq!(b.try_dynamic_usage(), Some(5));
+        assert_eq!(b.dynamic_usage_used(), 0);
+        assert_eq!(Bound::<String>::Unbounded.try_dynamic_usage(), Some(0));
+
+        let shared = Rc::new(vec![0u8; 100]);
+        let single = shared.dedup_dynamic_usage(&mut Visited::new());
+        let c = [Bound::Included(shared.clone()), Bound::Excluded(shared)];
+        assert!(Bound::Included(Overcounting).may_overcount());
+        assert_eq!(c.dedup_dynamic_usage(&mut Visited::new()), single);
+    }
+
+    #[cfg(memuse_allocator_api)]
+    #[test]
+    fn allocator_api() {
+        use alloc::alloc::Global;
+
+        let a: Vec<u64, Global> = Vec::with_capacity_in(4, Global);
+        assert_eq!(a.dynamic_usage(), 32);
+        assert_eq!(a.dynamic_usage_bounds(), (32, Some(32)));
+
+        let b = Box::new_in(a, Global);
+        let expected = mem::size_of::<Vec<u64, Global>>() + 32;
+        assert_eq!(b.dynamic_usage(), expected);
+        assert_eq!(b.dynamic_usage_bounds(), (expected, Some(expected)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn cursor() {
+        use std::io::{Cursor, Read};
+
+        let a = Cursor::new(Vec::<u8>::with_capacity(512));
+        assert_eq!(a.dynamic_usage(), 512);
+        assert_eq!(a.dynamic_usage_bounds(), (512, Some(512)));
+
+        // Reading advances the position without changing the buffer.
+        let mut b = Cursor::new(vec![0u8; 64].into_boxed_slice());
+        let mut buf = [0u8; 16];
+        b.read_exact(&mut buf).unwrap();
+        assert_eq!(b.position(), 16);
+        assert_eq!(b.dynamic_usage(), 64);
+        assert_eq!(b.dynamic_usage_bounds(), (64, Some(64)));
+
+        // The other methods are forwarded to the buffer.
+        let mut c = Cursor::new(Vec::<u8>::with_capacity(512));
+        c.get_mut().extend_from_slice(&[0; 10]);
+        assert_eq!(c.dynamic_usage_used(), 10);
+        assert_eq!(c.try_dynamic_usage(), Some(512));
+
+        let shared = Rc::new(vec![0u8; 100]);
+        let single = shared.dedup_dynamic_usage(&mut Visited::new());
+        let d = [Cursor::new(shared.clone()), Cursor::new(shared)];
+        assert!(Cursor::new(Overcounting).may_overcount());
+        assert_eq!(d.dedup_dynamic_usage(&mut Visited::new()), single);
+    }
+
+    #[test]
+    fn dynamic_usage_used() {
+        let mut a: Vec<String> = Vec::with_capacity(4);
+        assert_eq!(a.dynamic_usage_used(), 0);
+
+        let mut s = String::with_capacity(10);
+        s.push_str("abc");
+        assert_eq!(s.dynamic_usage_used(), 3);
+        a.push(s);
+        assert_eq!(a.dynamic_usage_used(), mem::size_of::<String>() + 3);
+        assert_eq!(a.dynamic_usage(), 4 * mem::size_of::<String>() + 10);
+
+        let mut b: VecDeque<u32> = VecDeque::with_capacity(8);
+        b.extend(0..3);
+        assert_eq!(b.dynamic_usage_used(), 3 * 4);
+
+        // Types without spare capacity are fully used.
+        let c: Box<[u8]> = vec![0u8; 5].into_boxed_slice();
+        assert_eq!(c.dynamic_usage_used(), c.dynamic_usage());
+
+        let d = (Vec::<u8>::with_capacity(10), vec![1u16, 2]);
+        assert_eq!(d.dynamic_usage_used(), 4);
+    }
+
+    #[test]
+    fn dynamic_usage_used_nested() {
+        // Spare capacity inside wrapped values is excluded at every level.
+        let s = || {
+            let mut s = String::with_capacity(10);
+            s.push_str("abc");
+            s
+        };
+
+        let a: Vec<Option<String>> = vec![Some(s()), None];
+        assert_eq!(
+            a.dynamic_usage_used(),
+            2 * mem::size_of::<Option<String>>() + 3
+        );
+        assert!(a.dynamic_usage_used() < a.dynamic_usage());
+
+        let b = Box::new(s());
+        assert_eq!(b.dynamic_usage_used(), mem::size_of::<String>() + 3);
+
+        let c = Rc::new(RefCell::new(vec![s()]));
+        assert_eq!(c.dynamic_usage_used(), c.dynamic_usage() - 7);
+
+        let d: Result<[String; 1], ()> = Ok([s()]);
+        assert_eq!(d.dynamic_usage_used(), 3);
+
+        let mut e = BTreeMap::new();
+        e.insert(1u32, s());
+        assert_eq!(e.dynamic_usage_used(), e.dynamic_usage() - 7);
+    }
+
+    #[test]
+    fn cell() {
+        let a = Cell::new(7u64);
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+    }
+
+    #[test]
+    fn refcell() {
+        let v = vec![7u8; 20];
+        let expected = v.dynamic_usage();
+        let a = RefCell::new(v);
+        assert_eq!(a.dynamic_usage(), expected);
+        assert_eq!(a.dynamic_usage_bounds(), (expected, Some(expected)));
+        assert_eq!(a.try_dynamic_usage(), Some(expected));
+
+        // Shared borrows don't prevent measurement.
+        let reader = a.borrow();
+        assert_eq!(a.try_dynamic_usage(), Some(expected));
+        drop(reader);
+
+        let mut writer = a.borrow_mut();
+        writer.push(8);
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, None));
+        assert_eq!(a.try_dynamic_usage(), None);
+        drop(writer);
+        assert_eq!(a.dynamic_usage(), a.borrow().capacity());
+    }
+
+    #[test]
+    fn refcell_nested_borrow() {
+        let a = RefCell::new(vec![
+            RefCell::new(String::with_capacity(16)),
+            RefCell::new(String::with_capacity(32)),
+        ]);
+        let outer = 2 * mem::size_of::<RefCell<String>>();
+        assert_eq!(a.dynamic_usage(), outer + 16 + 32);
+
+        // Only the contents of the mutably borrowed cell are not measured.
+        let inner = a.borrow();
+        let _writer = inner[0].borrow_mut();
+        assert_eq!(a.dynamic_usage(), outer + 32);
+        assert_eq!(a.try_dynamic_usage(), None);
+    }
+
+    #[test]
+    fn array() {
+        let a = [7; 42];
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let mut b = [None, None, None, None];
+        assert_eq!(b.dynamic_usage(), 0);
+        assert_eq!(b.dynamic_usage_bounds(), (0, Some(0)));
+
+        b[0] = Some(vec![4u8; 20]);
+        assert_eq!(b.dynamic_usage(), 20);
+        assert_eq!(b.dynamic_usage_bounds(), (20, Some(20)));
+    }
+
+    #[test]
+    fn vec() {
+        let capacity = 7;
+        let mut a = Vec::with_capacity(capacity);
+        a.push(42u64);
+
+        let expected = capacity * mem::size_of::<u64>();
+        assert_eq!(a.dynamic_usage(), expected);
+        assert_eq!(a.dynamic_usage_bounds(), (expected, Some(expected)));
+    }
+
+    #[test]
+    fn vecdeque_container_usage() {
+        let mut a: VecDeque<u64> = VecDeque::new();
+        assert_eq!(a.utilization(), 1.0);
+
+        a.reserve_exact(8);
+        a.extend(0..2);
+        assert_eq!(a.utilization(), 2.0 / a.capacity() as f64);
+
+        let minimal = a.minimal_usage();
+        a.shrink_to_fit();
+        assert_eq!(a.dynamic_usage(), minimal);
+        assert_eq!(a.shrink_savings(), 0);
+    }
+
+    #[test]
+    fn deep_usage() {
+        let a: Vec<u64> = Vec::with_capacity(4);
+        assert_eq!(super::deep_usage(&a), mem::size_of::<Vec<u64>>() + 32);
+        assert_eq!(super::deep_usage(&a), a.total_usage());
+
+        // A boxed value's header is on the heap, so the box's usage includes it.
+        let b = Box::new(a);
+        assert_eq!(b.dynamic_usage(), super::deep_usage(&*b));
+        assert_eq!(
+            super::deep_usage(&b),
+            mem::size_of::<Box<Vec<u64>>>() + mem::size_of::<Vec<u64>>() + 32
+        );
+
+        // Unsized values have no header, only their contents.
+        let c = String::from("hello");
+        assert_eq!(super::deep_usage(c.as_str()), 5);
+        assert_eq!(super::deep_usage(&[1u16, 2, 3][..]), 6);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn measure_iter() {
+        use std::collections::HashMap;
+
+        assert_eq!(
+            super::measure_iter(Vec::<Vec<u8>>::new()),
+            (0, (0, Some(0)))
+        );
+
+        let (usage, bounds) = super::measure_iter((0..100).map(|i| vec![0u8; i]));
+        assert_eq!(usage, (0..100).sum::<usize>());
+        assert_eq!(bounds, (usage, Some(usage)));
+
+        // The upper bound is unknown if any value's upper bound is.
+        let maps = (1..4).map(|i| HashMap::<u64, u64>::with_capacity(i * 10));
+        let expected: usize = (1..4)
+            .map(|i| HashMap::<u64, u64>::with_capacity(i * 10).dynamic_usage())
+            .sum();
+        let (usage, (lower, upper)) = super::measure_iter(maps);
+        assert_eq!(usage, expected);
+        assert!(lower <= usage);
+        assert_eq!(upper, None);
+    }
+
+    #[test]
+    fn usage_guard() {
+        let mut a: Vec<String> = Vec::with_capacity(2);
+        {
+            let mut guard = UsageGuard::new(&mut a);
+            let initial = 2 * mem::size_of::<String>();
+            assert_eq!(guard.initial_usage(), initial);
+            assert_eq!(guard.delta(), 0);
+
+            guard.push(String::with_capacity(10));
+            assert_eq!(guard.delta(), 10);
+
+            guard.shrink_to_fit();
+            assert_eq!(guard.delta(), 10 - mem::size_of::<String>() as isize);
+
+            guard.clear();
+            guard.shrink_to_fit();
+            assert_eq!(guard.delta(), -(initial as isize));
+        }
+        assert_eq!(a.dynamic_usage(), 0);
+
+        let reported = Cell::new(None);
+        {
+            let mut guard = UsageGuard::with_report(&mut a, |initial, usage| {
+                reported.set(Some((initial, usage)))
+            });
+            guard.push(String::with_capacity(10));
+            assert_eq!(reported.get(), None);
+        }
+        assert_eq!(reported.get(), Some((0, a.dynamic_usage())));
+    }
+
+    #[cfg(feature = "allocative")]
+    #[test]
+    fn allocative() {
+        use allocative::{size_of_unique, size_of_unique_allocated_data};
+
+        let a = AllocativeAdapter(42u64);
+        assert_eq!(size_of_unique_allocated_data(&a), 0);
+        assert_eq!(size_of_unique(&a), mem::size_of::<u64>());
+
+        let b = AllocativeAdapter(vec![String::with_capacity(10), String::with_capacity(20)]);
+        let expected = b.0.dynamic_usage();
+        assert_eq!(expected, 2 * mem::size_of::<String>() + 30);
+        assert_eq!(size_of_unique_allocated_data(&b), expected);
+        assert_eq!(size_of_unique(&b), mem::size_of::<Vec<String>>() + expected);
+    }
+
+    #[cfg(feature = "arrayvec")]
+    #[test]
+    fn arrayvec() {
+        let a = arrayvec::ArrayString::<32>::from("deadbeef").unwrap();
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let mut b = arrayvec::ArrayVec::<Vec<u8>, 4>::new();
+        assert_eq!(b.dynamic_usage(), 0);
+        assert_eq!(b.dynamic_usage_bounds(), (0, Some(0)));
+
+        // Only the heap memory of the elements is counted.
+        b.push(vec![0; 10]);
+        b.push(Vec::with_capacity(20));
+        assert_eq!(b.dynamic_usage(), 30);
+        assert_eq!(b.dynamic_usage_bounds(), (30, Some(30)));
+    }
+
+    #[cfg(feature = "ascii")]
+    #[test]
+    fn ascii() {
+        use ascii::{AsciiStr, AsciiString};
+
+        let a = AsciiStr::from_ascii("foobar").unwrap();
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let mut b = AsciiString::with_capacity(10);
+        b.push_str(a);
+        assert_eq!(b.dynamic_usage(), 10);
+        assert_eq!(b.dynamic_usage_bounds(), (10, Some(10)));
+    }
+
+    #[cfg(feature = "bitvec")]
+    #[test]
+    fn bitvec() {
+        use bitvec::prelude::*;
+
+        let a = bitarr![u32, Lsb0; 0; 80];
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        // 1000 bits fit in 125 bytes.
+        let mut b = BitVec::<u8, Lsb0>::with_capacity(1000);
+        b.resize(1000, true);
+        assert_eq!(b.dynamic_usage(), 125);
+        assert_eq!(b.dynamic_usage_bounds(), (125, Some(125)));
+
+        // With a wider store, the usage is rounded up to whole words.
+        let c = BitVec::<u64, Msb0>::with_capacity(1000);
+        assert_eq!(c.dynamic_usage(), 16 * 8);
+        assert_eq!(c.dynamic_usage_bounds(), (128, Some(128)));
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn bytes() {
+        use bytes::{Bytes, BytesMut};
+
+        let a = BytesMut::with_capacity(1024);
+        assert_eq!(a.dynamic_usage(), 1024);
+        assert_eq!(a.dynamic_usage_bounds(), (1024, Some(1024)));
+
+        let b = Bytes::from(vec![0u8; 100]);
+        assert_eq!(b.dynamic_usage(), 100);
+        assert_eq!(b.dynamic_usage_bounds(), (0, None));
+        assert!(!b.may_overcount());
+
+        // Clones share the buffer, so their usage over-counts it.
+        let c = b.clone();
+        assert_eq!(c.dynamic_usage(), 100);
+        assert!(b.may_overcount());
+        assert!(c.may_overcount());
+
+        let d = Bytes::from_static(b"static");
+        assert_eq!(d.dynamic_usage(), 6);
+        assert!(d.may_overcount());
+    }
+
+    #[cfg(feature = "compact_str")]
+    #[test]
+    fn compact_str() {
+        use compact_str::CompactString;
+
+        let a = CompactString::new("short");
+        assert!(!a.is_heap_allocated());
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let b = CompactString::const_new("a long static string that is not heap-allocated");
+        assert!(!b.is_heap_allocated());
+        assert_eq!(b.dynamic_usage(), 0);
+
+        let c = CompactString::with_capacity(128);
+        assert!(c.is_heap_allocated());
+        assert_eq!(c.dynamic_usage(), 128);
+        assert_eq!(c.dynamic_usage_bounds(), (128, Some(128)));
+
+        // Strings longer than the inline storage spill to the heap.
+        let mut d = CompactString::default();
+        for _ in 0..mem::size_of::<String>() {
+            d.push('a');
+        }
+        assert!(!d.is_heap_allocated());
+        d.push('a');
+        assert!(d.is_heap_allocated());
+        assert_eq!(d.dynamic_usage(), d.capacity());
+    }
+
+    #[cfg(feature = "deepsize")]
+    #[test]
+    fn deepsize() {
+        use deepsize::DeepSizeOf;
+
+        let a = DeepSizeAdapter(42u64);
+        assert_eq!(a.deep_size_of(), mem::size_of::<u64>());
+
+        // deepsize measures the strings' capacities, and their inline size in the Vec.
+        let strings = vec![String::with_capacity(10), String::with_capacity(20)];
+        let expected = strings.deep_size_of();
+        let b = DeepSizeAdapter(strings);
+        assert_eq!(b.deep_size_of(), expected);
+    }
+
+    #[cfg(feature = "ecow")]
+    #[test]
+    fn ecow() {
+        use ecow::{EcoString, EcoVec};
+
+        let a = EcoString::inline("short");
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, None));
+
+        let long = "a string that is too long to be stored inline";
+        let b = EcoString::from(long);
+        assert_eq!(b.dynamic_usage(), long.len());
+        assert_eq!(b.dynamic_usage_bounds(), (0, None));
+
+        // Short strings stay on the heap after truncation, and in clones of them.
+        let mut short = EcoString::from(long);
+        short.truncate(5);
+        assert!(short.len() <= EcoString::INLINE_LIMIT);
+        assert_eq!(short.dynamic_usage(), 5);
+        assert!(short.may_overcount());
+        assert_eq!(short.clone().dynamic_usage(), 5);
+
+        let c: EcoVec<u64> = EcoVec::new();
+        assert_eq!(c.dynamic_usage(), 0);
+
+        let d: EcoVec<String> = EcoVec::from(["foobar".to_string()]);
+        let header = 2 * mem::size_of::<usize>();
+        let expected = header + d.capacity() * mem::size_of::<String>() + 6;
+        assert_eq!(d.dynamic_usage(), expected);
+        assert_eq!(d.dynamic_usage_bounds(), (0, None));
+
+        assert!(!a.may_overcount());
+        assert!(b.may_overcount());
+        assert!(!c.may_overcount());
+        assert!(d.may_overcount());
+    }
+
+    #[cfg(feature = "ecow")]
+    #[test]
+    fn may_overcount() {
+        use ecow::EcoVec;
+
+        let shared: EcoVec<u8> = EcoVec::from([1, 2, 3]);
+
+        let mut a: Vec<Option<(u32, EcoVec<u8>)>> = vec![None, Some((1, EcoVec::new()))];
+        assert!(!a.may_overcount());
+        a.push(Some((2, shared.clone())));
+        assert!(a.may_overcount());
+
+        let b: Result<Box<[EcoVec<u8>; 1]>, String> = Ok(Box::new([shared]));
+        assert!(b.may_overcount());
+    }
+
+    #[cfg(all(feature = "ahash", feature = "std"))]
+    #[test]
+    fn ahash() {
+        use std::collections::{HashMap, HashSet};
+
+        let a: HashMap<u64, Vec<u8>> = HashMap::with_capacity(12);
+        let b: HashMap<u64, Vec<u8>, ahash::RandomState> =
+            HashMap::with_capacity_and_hasher(12, ahash::RandomState::new());
+        assert_eq!(a.dynamic_usage(), b.dynamic_usage());
+        assert_eq!(a.dynamic_usage_bounds(), b.dynamic_usage_bounds());
+
+        let c: HashSet<String> = HashSet::with_capacity(12);
+        let d: HashSet<String, ahash::RandomState> =
+            HashSet::with_capacity_and_hasher(12, ahash::RandomState::new());
+        assert_eq!(c.dynamic_usage(), d.dynamic_usage());
+    }
+
+    #[cfg(all(feature = "foldhash", feature = "std"))]
+    #[test]
+    fn foldhash() {
+        use std::collections::HashMap;
+
+        let a: HashMap<u64, Vec<u8>> = HashMap::with_capacity(12);
+        let b: HashMap<u64, Vec<u8>, foldhash::fast::RandomState> =
+            HashMap::with_capacity_and_hasher(12, Default::default());
+        let c: HashMap<u64, Vec<u8>, foldhash::quality::RandomState> =
+            HashMap::with_capacity_and_hasher(12, Default::default());
+        assert_eq!(a.dynamic_usage(), b.dynamic_usage());
+        assert_eq!(a.dynamic_usage(), c.dynamic_usage());
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn heapless() {
+        // A full heapless::Vec doesn't use the heap.
+        let a: heapless::Vec<u8, 32> = (0..32).collect();
+        assert!(a.is_full());
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let mut b = heapless::String::<16>::new();
+        b.push_str("firmware").unwrap();
+        assert_eq!(b.dynamic_usage(), 0);
+        assert_eq!(b.dynamic_usage_bounds(), (0, Some(0)));
+
+        // Only the heap memory of the elements is counted.
+        let mut c = heapless::Vec::<Vec<u8>, 4>::new();
+        c.push(vec![0; 10]).unwrap();
+        c.push(Vec::with_capacity(20)).unwrap();
+        assert_eq!(c.dynamic_usage(), 30);
+        assert_eq!(c.dynamic_usage_bounds(), (30, Some(30)));
+
+        let mut d = heapless::FnvIndexMap::<u8, String, 8>::new();
+        assert_eq!(d.dynamic_usage(), 0);
+        d.insert(1, String::with_capacity(5)).unwrap();
+        d.insert(2, String::with_capacity(7)).unwrap();
+        assert_eq!(d.dynamic_usage(), 12);
+        assert_eq!(d.dynamic_usage_bounds(), (12, Some(12)));
+        assert_eq!(d.try_dynamic_usage(), Some(12));
+    }
+
+    #[cfg(feature = "im")]
+    #[test]
+    fn im_vector() {
+        let a = im::Vector::<u64>::new();
+        assert!(a.is_inline());
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, None));
+        assert!(!a.may_overcount());
+
+        // 1000 elements fill 16 chunks of 64, under a single branch node.
+        let b: im::Vector<u64> = (0..1000).collect();
+        let leaves = 16 * 64 * 8;
+        let branches = 64 * mem::size_of::<usize>();
+        assert_eq!(b.dynamic_usage(), leaves + branches);
+        assert_eq!(b.dynamic_usage_bounds(), (leaves, None));
+        assert!(b.may_overcount());
+
+        // Clones share their nodes, but are each measured in full.
+        let c = b.clone();
+        assert_eq!(c.dynamic_usage(), b.dynamic_usage());
+
+        // The heap memory of the elements is counted.
+        let d: im::Vector<Vec<u8>> = (0..100).map(|_| Vec::with_capacity(10)).collect();
+        let leaves = 2 * 64 * mem::size_of::<Vec<u8>>();
+        let branches = 64 * mem::size_of::<usize>();
+        assert_eq!(d.dynamic_usage(), leaves + branches + 1000);
+        assert_eq!(d.dynamic_usage_bounds(), (leaves + 1000, None));
+    }
+
+    #[cfg(feature = "im")]
+    #[test]
+    fn im_hashmap() {
+        let node = 32 * mem::size_of::<((u64, String), u32)>() + 4;
+
+        // The root node is always allocated.
+        let mut a = im::HashMap::<u64, String>::new();
+        let hasher = a.hasher().dynamic_usage();
+        assert_eq!(a.dynamic_usage(), node + hasher);
+        assert_eq!(a.dynamic_usage_bounds(), (node + hasher, None));
+        assert!(a.may_overcount());
+
+        // 100 entries fill at least 4 nodes, under a single branch node.
+        for i in 0..100 {
+            a.insert(i, String::with_capacity(3));
+        }
+        assert_eq!(a.dynamic_usage(), 5 * node + hasher + 300);
+        assert_eq!(a.dynamic_usage_bounds(), (4 * node + hasher + 300, None));
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn smallvec() {
+        let mut a = smallvec::SmallVec::<[u64; 2]>::new();
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        a.push(1);
+        a.push(2);
+        assert!(!a.spilled());
+        assert_eq!(a.dynamic_usage(), 0);
+
+        a.push(3);
+        assert!(a.spilled());
+        let expected = a.capacity() * 8;
+        assert!(expected >= 24);
+        assert_eq!(a.dynamic_usage(), expected);
+        assert_eq!(a.dynamic_usage_bounds(), (expected, Some(expected)));
+
+        // Inline elements can still allocate.
+        let b: smallvec::SmallVec<[Vec<u8>; 2]> = smallvec::smallvec![vec![0; 10]];
+        assert_eq!(b.dynamic_usage(), 10);
+    }
+
+    #[cfg(feature = "tinyvec")]
+    #[test]
+    fn tinyvec() {
+        use tinyvec::{array_vec, tiny_vec, ArrayVec, TinyVec};
+
+        let a: ArrayVec<[u64; 4]> = array_vec![1, 2, 3];
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        // Inline elements can still allocate.
+        let mut b = ArrayVec::<[Vec<u8>; 2]>::new();
+        b.push(vec![0; 10]);
+        assert_eq!(b.dynamic_usage(), 10);
+
+        let mut c: TinyVec<[u64; 2]> = tiny_vec![1, 2];
+        assert!(c.is_inline());
+        assert_eq!(c.dynamic_usage(), 0);
+        assert_eq!(c.dynamic_usage_bounds(), (0, Some(0)));
+
+        c.push(3);
+        assert!(c.is_heap());
+        let expected = c.capacity() * 8;
+        assert!(expected >= 24);
+        assert_eq!(c.dynamic_usage(), expected);
+        assert_eq!(c.dynamic_usage_bounds(), (expected, Some(expected)));
+    }
+
+    #[cfg(feature = "url")]
+    #[test]
+    fn url() {
+        let a = url::Url::parse("https://example.com/crawl?page=2").unwrap();
+        assert_eq!(a.dynamic_usage(), 32);
+        assert_eq!(a.dynamic_usage_bounds(), (32, None));
+
+        // The serialization is normalized when parsing.
+        let b = url::Url::parse("HTTPS://EXAMPLE.COM").unwrap();
+        assert_eq!(b.as_str(), "https://example.com/");
+        assert_eq!(b.dynamic_usage(), 20);
+    }
+
+    #[cfg(all(feature = "uuid", feature = "std"))]
+    #[test]
+    fn uuid() {
+        use std::collections::HashMap;
+
+        let a = uuid::Uuid::from_u128(0x1234);
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        let mut b: HashMap<uuid::Uuid, Vec<u8>> = HashMap::with_capacity(4);
+        b.insert(a, vec![0; 10]);
+        let c: HashMap<[u8; 16], Vec<u8>> = HashMap::with_capacity(4);
+        assert_eq!(b.dynamic_usage(), c.dynamic_usage() + 10);
+    }
+
+    #[cfg(feature = "weak-table")]
+    #[test]
+    fn weak_value_hash_map() {
+        use alloc::sync::{Arc, Weak};
+        use alloc::vec;
+        use weak_table::WeakValueHashMap;
+
+        let mut map: WeakValueHashMap<u32, Weak<Vec<u8>>> = WeakValueHashMap::new();
+        let empty = map.dynamic_usage();
+
+        let live = Arc::new(vec![0u8; 100]);
+        let dead = Arc::new(vec![0u8; 1000]);
+        map.insert(1, live.clone());
+        map.insert(2, live.clone());
+        map.insert(3, dead.clone());
+        drop(dead);
+
+        // The live value is counted once, even though it is stored under two keys.
+        let table = map.capacity() * mem::size_of::<Option<(u32, Weak<Vec<u8>>, u64)>>();
+        let shared = 2 * mem::size_of::<usize>() + mem::size_of::<Vec<u8>>() + 100;
+        assert!(empty <= table);
+        assert_eq!(map.dynamic_usage(), table + shared);
+        assert_eq!(map.dynamic_usage_bounds(), (table, None));
+
+        // Measuring the map does not keep the live value alive.
+        assert_eq!(Arc::strong_count(&live), 1);
+        drop(live);
+        assert_eq!(map.dynamic_usage(), table);
+    }
+
+    #[cfg(feature = "nonempty")]
+    #[test]
+    fn nonempty() {
+        let a = nonempty::NonEmpty::new(42);
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+        const CAPACITY: usize = 7;
+        let b = nonempty::NonEmpty::from_slice(&[27u128; CAPACITY]).unwrap();
+
+        let expected = (CAPACITY - 1) * mem::size_of::<u128>();
+        assert_eq!(b.dynamic_usage(), expected);
+        assert_eq!(b.dynamic_usage_bounds(), (expected, Some(expected)));
+    }
+
+    #[cfg(feature = "num-bigint")]
+    #[test]
+    fn num_bigint() {
+        use num_bigint::{BigInt, BigUint, Sign};
+
+        let zero = BigUint::default();
+        assert_eq!(zero.dynamic_usage(), 0);
+        assert_eq!(zero.dynamic_usage_bounds(), (0, None));
+
+        // A 256-bit value has 4 u64 limbs (or 8 u32 limbs).
+        let a = BigUint::from_bytes_be(&[0xff; 32]);
+        assert_eq!(a.bits(), 256);
+        assert_eq!(a.iter_u64_digits().count(), 4);
+        assert_eq!(a.dynamic_usage(), 32);
+        assert_eq!(a.dynamic_usage_bounds(), (32, None));
+
+        let b = BigInt::from_biguint(Sign::Minus, a);
+        assert_eq!(b.dynamic_usage(), 32);
+        assert_eq!(b.dynamic_usage_bounds(), (32, None));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex() {
+        let pattern = "^[0-9]{4}-[0-9]{2}-[0-9]{2}$";
+        let a = regex::Regex::new(pattern).unwrap();
+        assert_eq!(
+            a.dynamic_usage(),
+            pattern.len() * REGEX_USAGE_PER_PATTERN_BYTE
+        );
+        assert_eq!(a.dynamic_usage_bounds(), (pattern.len(), None));
+
+        // Longer patterns are estimated to use more memory.
+        let b = regex::Regex::new("^[0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9]{2}:[0-9]{2}$").unwrap();
+        assert!(b.dynamic_usage() > a.dynamic_usage());
     }
 }
@@ -0,0 +1,177 @@
+//! `DynamicUsage` impls for `Mutex` and `RwLock`.
+//!
+//! Measuring the contents of a lock requires acquiring it. These impls never block:
+//! if the lock is currently held elsewhere (including by the calling thread), the
+//! contents are not measured: `dynamic_usage` returns 0, `dynamic_usage_bounds` returns
+//! `(0, None)`, and `try_dynamic_usage` returns `None`. An `RwLock` can be measured while
+//! other readers hold it, but not while a writer does.
+//!
+//! A lock that is poisoned (because a thread panicked while holding it) is measured as
+//! normal. Poisoning doesn't change the memory held by the inner value.
+
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, TryLockError};
+
+use crate::{DynamicUsage, Visited};
+
+fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        // Poisoning doesn't affect the memory held by the inner value.
+        Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+fn try_read<T>(lock: &RwLock<T>) -> Option<RwLockReadGuard<'_, T>> {
+    match lock.try_read() {
+        Ok(guard) => Some(guard),
+        // Poisoning doesn't affect the memory held by the inner value.
+        Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+impl<T: DynamicUsage> DynamicUsage for Mutex<T> {
+    fn dynamic_usage(&self) -> usize {
+        try_lock(self).map_or(0, |inner| inner.dynamic_usage())
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        try_lock(self)
+            .map(|inner| inner.dynamic_usage_bounds())
+            .unwrap_or((0, None))
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        try_lock(self).and_then(|inner| inner.try_dynamic_usage())
+    }
+
+    fn may_overcount(&self) -> bool {
+        try_lock(self).map_or(false, |inner| inner.may_overcount())
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        try_lock(self).map_or(0, |inner| inner.dedup_dynamic_usage(visited))
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        try_lock(self).map_or(0, |inner| inner.dynamic_usage_used())
+    }
+}
+
+impl<T: DynamicUsage> DynamicUsage for RwLock<T> {
+    fn dynamic_usage(&self) -> usize {
+        try_read(self).map_or(0, |inner| inner.dynamic_usage())
+    }
+
+    fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+        try_read(self)
+            .map(|inner| inner.dynamic_usage_bounds())
+            .unwrap_or((0, None))
+    }
+
+    fn try_dynamic_usage(&self) -> Option<usize> {
+        try_read(self).and_then(|inner| inner.try_dynamic_usage())
+    }
+
+    fn may_overcount(&self) -> bool {
+        try_read(self).map_or(false, |inner| inner.may_overcount())
+    }
+
+    fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+        try_read(self).map_or(0, |inner| inner.dedup_dynamic_usage(visited))
+    }
+
+    fn dynamic_usage_used(&self) -> usize {
+        try_read(self).map_or(0, |inner| inner.dynamic_usage_used())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use core::mem;
+
+    use super::*;
+
+    #[test]
+    fn mutex() {
+        let a = Mutex::new(Vec::<u64>::with_capacity(4));
+        assert_eq!(a.dynamic_usage(), 32);
+        assert_eq!(a.dynamic_usage_bounds(), (32, Some(32)));
+        assert_eq!(a.try_dynamic_usage(), Some(32));
+
+        let _guard = a.lock().unwrap();
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, None));
+        assert_eq!(a.try_dynamic_usage(), None);
+    }
+
+    #[test]
+    fn mutex_string() {
+        let a = Mutex::new(String::with_capacity(16));
+        {
+            let mut guard = a.lock().unwrap();
+            guard.push_str("contents");
+
+            // The contents can't be measured while we hold the lock.
+            assert_eq!(a.try_dynamic_usage(), None);
+        }
+        assert_eq!(a.dynamic_usage(), 16);
+        assert_eq!(a.dynamic_usage_bounds(), (16, Some(16)));
+        assert_eq!(a.dynamic_usage_used(), 8);
+
+        let b = RwLock::new(Some(String::with_capacity(16)));
+        b.write().unwrap().as_mut().unwrap().push_str("abc");
+        assert_eq!(b.dynamic_usage_used(), 3);
+    }
+
+    #[test]
+    fn nested_lock_held() {
+        let a = Mutex::new(vec![
+            Mutex::new(String::with_capacity(16)),
+            Mutex::new(String::with_capacity(32)),
+        ]);
+        let outer = 2 * mem::size_of::<Mutex<String>>();
+        assert_eq!(a.dynamic_usage(), outer + 16 + 32);
+
+        // Leave the first inner lock held after releasing the outer lock. Only its
+        // contents are not measured.
+        mem::forget(a.lock().unwrap()[0].lock().unwrap());
+        assert_eq!(a.dynamic_usage(), outer + 32);
+        assert_eq!(a.try_dynamic_usage(), None);
+
+        let b = RwLock::new(vec![RwLock::new(String::with_capacity(16))]);
+        mem::forget(b.read().unwrap()[0].write().unwrap());
+        assert_eq!(b.dynamic_usage(), mem::size_of::<RwLock<String>>());
+    }
+
+    #[test]
+    fn mutex_poisoned() {
+        let a = Mutex::new(String::with_capacity(16));
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = a.lock().unwrap();
+            panic!("poison the lock");
+        });
+        assert!(a.is_poisoned());
+        assert_eq!(a.dynamic_usage(), 16);
+        assert_eq!(a.try_dynamic_usage(), Some(16));
+    }
+
+    #[test]
+    fn rwlock() {
+        let a = RwLock::new(Vec::<u64>::with_capacity(4));
+        assert_eq!(a.try_dynamic_usage(), Some(32));
+
+        // Other readers don't prevent measurement.
+        let reader = a.read().unwrap();
+        assert_eq!(a.try_dynamic_usage(), Some(32));
+        drop(reader);
+
+        let _writer = a.write().unwrap();
+        assert_eq!(a.dynamic_usage(), 0);
+        assert_eq!(a.dynamic_usage_bounds(), (0, None));
+        assert_eq!(a.try_dynamic_usage(), None);
+    }
+}
@@ -1,6 +1,4 @@
-use core::array;
-
-use crate::DynamicUsage;
+use crate::{DynamicUsage, Visited};
 
 macro_rules! tuple_impls {
     ($(
@@ -12,11 +10,11 @@ macro_rules! tuple_impls {
             impl<$($T),+> DynamicUsage for ($($T,)+) where $($T: DynamicUsage),+
             {
                 fn dynamic_usage(&self) -> usize {
-                    array::IntoIter::new([$(self.$idx.dynamic_usage()),+]).sum::<usize>()
+                    IntoIterator::into_iter([$(self.$idx.dynamic_usage()),+]).sum::<usize>()
                 }
 
                 fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
-                    array::IntoIter::new([$(self.$idx.dynamic_usage_bounds()),+])
+                    IntoIterator::into_iter([$(self.$idx.dynamic_usage_bounds()),+])
                         .fold(
                             (0, Some(0)),
                             |(acc_lower, acc_upper), (lower, upper)| {
@@ -24,6 +22,22 @@ macro_rules! tuple_impls {
                             },
                         )
                 }
+
+                fn try_dynamic_usage(&self) -> Option<usize> {
+                    Some(0 $(+ self.$idx.try_dynamic_usage()?)+)
+                }
+
+                fn may_overcount(&self) -> bool {
+                    false $(|| self.$idx.may_overcount())+
+                }
+
+                fn dedup_dynamic_usage(&self, visited: &mut Visited) -> usize {
+                    0 $(+ self.$idx.dedup_dynamic_usage(visited))+
+                }
+
+                fn dynamic_usage_used(&self) -> usize {
+                    0 $(+ self.$idx.dynamic_usage_used())+
+                }
             }
         )+
     };
@@ -134,6 +148,72 @@ tuple_impls! {
         (10) -> K
         (11) -> L
     }
+    Tuple13 {
+        (0) -> A
+        (1) -> B
+        (2) -> C
+        (3) -> D
+        (4) -> E
+        (5) -> F
+        (6) -> G
+        (7) -> H
+        (8) -> I
+        (9) -> J
+        (10) -> K
+        (11) -> L
+        (12) -> M
+    }
+    Tuple14 {
+        (0) -> A
+        (1) -> B
+        (2) -> C
+        (3) -> D
+        (4) -> E
+        (5) -> F
+        (6) -> G
+        (7) -> H
+        (8) -> I
+        (9) -> J
+        (10) -> K
+        (11) -> L
+        (12) -> M
+        (13) -> N
+    }
+    Tuple15 {
+        (0) -> A
+        (1) -> B
+        (2) -> C
+        (3) -> D
+        (4) -> E
+        (5) -> F
+        (6) -> G
+        (7) -> H
+        (8) -> I
+        (9) -> J
+        (10) -> K
+        (11) -> L
+        (12) -> M
+        (13) -> N
+        (14) -> O
+    }
+    Tuple16 {
+        (0) -> A
+        (1) -> B
+        (2) -> C
+        (3) -> D
+        (4) -> E
+        (5) -> F
+        (6) -> G
+        (7) -> H
+        (8) -> I
+        (9) -> J
+        (10) -> K
+        (11) -> L
+        (12) -> M
+        (13) -> N
+        (14) -> O
+        (15) -> P
+    }
 }
 
 #[cfg(feature = "std")]
@@ -165,4 +245,53 @@ mod tests {
         assert_eq!(c.dynamic_usage(), lower_bound);
         assert_eq!(c.dynamic_usage_bounds(), (lower_bound, None));
     }
+
+    #[test]
+    fn vec_of_tuples() {
+        use alloc::string::String;
+
+        let mut a: Vec<(String, Vec<u8>)> = Vec::with_capacity(4);
+        a.push((String::with_capacity(5), Vec::with_capacity(100)));
+        a.push((String::with_capacity(7), Vec::with_capacity(0)));
+        a.push((String::new(), Vec::with_capacity(33)));
+
+        let expected = 4 * core::mem::size_of::<(String, Vec<u8>)>() + (5 + 7) + (100 + 33);
+        assert_eq!(a.dynamic_usage(), expected);
+        assert_eq!(a.dynamic_usage_bounds(), (expected, Some(expected)));
+        assert_eq!(a.try_dynamic_usage(), Some(expected));
+
+        // Unknown upper bounds of nested collections propagate through the tuples.
+        let b: Vec<(String, HashSet<u8>)> =
+            vec![(String::with_capacity(5), HashSet::with_capacity(10))];
+        let (lower, upper) = b.dynamic_usage_bounds();
+        assert_eq!(lower, b.dynamic_usage());
+        assert_eq!(upper, None);
+    }
+
+    #[test]
+    fn tuple16() {
+        let a = (
+            Vec::<u8>::with_capacity(1),
+            alloc::string::String::with_capacity(2),
+            Vec::<u8>::with_capacity(3),
+            Vec::<u8>::with_capacity(4),
+            Vec::<u8>::with_capacity(5),
+            Vec::<u8>::with_capacity(6),
+            Vec::<u8>::with_capacity(7),
+            Vec::<u8>::with_capacity(8),
+            Vec::<u8>::with_capacity(9),
+            Vec::<u8>::with_capacity(10),
+            Vec::<u8>::with_capacity(11),
+            Vec::<u8>::with_capacity(12),
+            Vec::<u8>::with_capacity(13),
+            Vec::<u8>::with_capacity(14),
+            Vec::<u8>::with_capacity(15),
+            Vec::<u64>::with_capacity(16),
+        );
+        // 1 + 2 + ... + 15 bytes, plus 16 u64s.
+        let expected = 120 + 128;
+        assert_eq!(a.dynamic_usage(), expected);
+        assert_eq!(a.dynamic_usage_bounds(), (expected, Some(expected)));
+        assert_eq!(a.try_dynamic_usage(), Some(expected));
+    }
 }
@@ -0,0 +1,154 @@
+//! Checks that the `BTreeMap` node layout mirrored by `memuse` still matches the one
+//! used by the standard library. If these tests fail after a toolchain upgrade, the
+//! layout has likely drifted.
+
+#![cfg(feature = "std")]
+
+use std::collections::BTreeMap;
+
+use memuse::DynamicUsage;
+
+mod common;
+
+#[global_allocator]
+static ALLOCATOR: common::TrackingAllocator = common::TrackingAllocator;
+
+fn check_bounds<K: DynamicUsage, V: DynamicUsage>(map: &BTreeMap<K, V>, allocated: usize) {
+    let (lower, upper) = map.dynamic_usage_bounds();
+    assert!(
+        lower <= allocated,
+        "{} entries: lower bound {} > allocated {}",
+        map.len(),
+        lower,
+        allocated,
+    );
+    assert!(
+        allocated <= upper.unwrap(),
+        "{} entries: upper bound {} < allocated {}",
+        map.len(),
+        upper.unwrap(),
+        allocated,
+    );
+}
+
+#[test]
+fn sequential_inserts() {
+    for entries in (0..2000).chain((2000..20_000).step_by(97)) {
+        let (map, allocated) = common::measure(|| {
+            let mut map = BTreeMap::new();
+            for i in 0..entries {
+                map.insert(i as u64, i as u64);
+            }
+            map
+        });
+        check_bounds(&map, allocated);
+    }
+}
+
+#[test]
+fn scattered_inserts() {
+    for entries in (0..2000).step_by(7).chain((2000..20_000).step_by(997)) {
+        let (map, allocated) = common::measure(|| {
+            let mut map = BTreeMap::new();
+            let mut key: u32 = 1;
+            for _ in 0..entries {
+                // A simple LCG, to insert keys in a scattered order.
+                key = key.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                map.insert(key, [0u8; 3]);
+            }
+            map
+        });
+        check_bounds(&map, allocated);
+    }
+}
+
+#[test]
+fn bulk_build() {
+    for entries in (0..2000).chain((2000..20_000).step_by(97)) {
+        let (map, allocated) =
+            common::measure(|| (0..entries).map(|i| (i, ())).collect::<BTreeMap<u16, ()>>());
+        check_bounds(&map, allocated);
+    }
+}
+
+#[test]
+fn height_boundaries() {
+    // The number of entries at which the height bounds change, for B = 6:
+    // - (2 * B)^h - 1 entries fit in a tree of height h - 1.
+    // - 2 * B^h - 1 entries are needed for a tree of height h.
+    let boundaries = [
+        11, 71, 143, 431, 1727, 2591, 15_551, 20_735, 93_311, 248_831,
+    ];
+
+    for &boundary in boundaries.iter() {
+        for entries in boundary - 3..=boundary + 3 {
+            let (map, allocated) = common::measure(|| {
+                let mut map = BTreeMap::new();
+                for i in 0..entries {
+                    map.insert(i as u64, i as u64);
+                }
+                map
+            });
+            check_bounds(&map, allocated);
+
+            let (map, allocated) =
+                common::measure(|| (0..entries).map(|i| (i, ())).collect::<BTreeMap<u32, ()>>());
+            check_bounds(&map, allocated);
+        }
+    }
+}
+
+#[test]
+fn removals() {
+    for entries in (0..2000).step_by(7) {
+        let (map, allocated) = common::measure(|| {
+            let mut map: BTreeMap<_, _> = (0..2000u64).map(|i| (i, i)).collect();
+            map.retain(|k, _| (k % 2000) < entries);
+            map
+        });
+        check_bounds(&map, allocated);
+    }
+}
+
+/// Returns the ratio of the estimated dynamic memory usage of the map built by `f`, to
+/// the heap memory that it actually allocated.
+fn estimate_accuracy<K: DynamicUsage, V: DynamicUsage>(f: impl FnOnce() -> BTreeMap<K, V>) -> f64 {
+    let (map, allocated) = common::measure(f);
+    map.dynamic_usage() as f64 / allocated as f64
+}
+
+#[test]
+fn accuracy() {
+    for &entries in &[100u32, 1000, 10_000, 100_000] {
+        let sequential = estimate_accuracy(|| {
+            let mut map = BTreeMap::new();
+            for i in 0..entries {
+                map.insert(i, i);
+            }
+            map
+        });
+        let scattered = estimate_accuracy(|| {
+            let mut map = BTreeMap::new();
+            let mut key: u32 = 1;
+            for _ in 0..entries {
+                key = key.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                map.insert(key, key);
+            }
+            map
+        });
+        let bulk = estimate_accuracy(|| (0..entries).map(|i| (i, i)).collect::<BTreeMap<_, _>>());
+        for &(layout, accuracy) in &[
+            ("sequential", sequential),
+            ("scattered", scattered),
+            ("bulk", bulk),
+        ] {
+            assert!(
+                (0.5..2.0).contains(&accuracy),
+                "{} entries ({}): accuracy {:.3}",
+                entries,
+                layout,
+                accuracy,
+            );
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! A global allocator that tracks the heap memory allocated by each thread, used to
+//! check `DynamicUsage` impls against the allocations that types actually make.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+}
+
+fn track(delta: isize) {
+    // Ignore allocations made while the thread is being torn down.
+    let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + delta));
+}
+
+pub struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            track(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        track(-(layout.size() as isize));
+    }
+}
+
+/// Returns the value constructed by `f`, along with the number of bytes of heap memory
+/// allocated by the current thread while constructing it that have not been freed.
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.with(Cell::get);
+    let value = f();
+    let after = ALLOCATED.with(Cell::get);
+    (value, (after - before) as usize)
+}
@@ -0,0 +1,117 @@
+//! Checks the estimates for standard library containers against the allocations they
+//! make.
+
+#![cfg(feature = "std")]
+
+use memuse::DynamicUsage;
+
+mod common;
+
+#[global_allocator]
+static ALLOCATOR: common::TrackingAllocator = common::TrackingAllocator;
+
+#[test]
+fn boxed_vec() {
+    for capacity in [0, 1, 7, 100].iter().copied() {
+        let (b, allocated) = common::measure(|| Box::new(Vec::<u64>::with_capacity(capacity)));
+        let expected = std::mem::size_of::<Vec<u64>>() + capacity * 8;
+        assert_eq!(b.dynamic_usage(), expected);
+        assert_eq!(b.dynamic_usage(), allocated);
+    }
+}
+
+#[test]
+fn rc() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    for len in [0, 1, 7, 100].iter().copied() {
+        let (a, allocated) = common::measure(|| Rc::new(vec![0u32; len]));
+        assert_eq!(a.dynamic_usage(), allocated);
+
+        let (b, allocated) = common::measure(|| Arc::new(vec![0u32; len]));
+        assert_eq!(b.dynamic_usage(), allocated);
+    }
+
+    for s in [
+        "",
+        "a",
+        "hello, world",
+        "a string longer than a couple of words",
+    ]
+    .iter()
+    {
+        let (a, allocated) = common::measure(|| Rc::<str>::from(*s));
+        assert_eq!(a.dynamic_usage(), allocated);
+
+        let (b, allocated) = common::measure(|| Arc::<str>::from(*s));
+        assert_eq!(b.dynamic_usage(), allocated);
+    }
+}
+
+#[test]
+fn vecdeque() {
+    use std::collections::VecDeque;
+
+    // Before Rust 1.67, the ring buffer left one slot empty.
+    let empty_slots = if cfg!(memuse_vecdeque_no_empty_slot) {
+        0
+    } else {
+        1
+    };
+
+    for capacity in [0, 1, 7, 8, 100].iter().copied() {
+        let (mut a, allocated) = common::measure(|| VecDeque::<u64>::with_capacity(capacity));
+        assert_eq!(a.dynamic_usage(), (a.capacity() + empty_slots) * 8);
+        assert_eq!(a.dynamic_usage(), allocated);
+
+        // Wrapping around the ring buffer within its capacity doesn't reallocate.
+        let cap = a.capacity() as u64;
+        a.extend(0..cap);
+        a.drain(..cap as usize / 2);
+        a.extend(0..cap / 2);
+        assert_eq!(a.dynamic_usage(), allocated);
+    }
+}
+
+#[test]
+fn linked_list() {
+    use std::collections::LinkedList;
+
+    for len in [0, 1, 7, 100].iter().copied() {
+        let (a, allocated) = common::measure(|| (0..len as u64).collect::<LinkedList<_>>());
+        // Each node holds the element and two pointers.
+        assert_eq!(
+            a.dynamic_usage(),
+            len * (8 + 2 * std::mem::size_of::<usize>())
+        );
+        assert_eq!(a.dynamic_usage(), allocated);
+
+        // Nodes are padded to the alignment of the pointers.
+        let (b, allocated) = common::measure(|| (0..len as u8).collect::<LinkedList<_>>());
+        assert_eq!(b.dynamic_usage(), allocated);
+
+        let (c, allocated) =
+            common::measure(|| (0..len).map(|i| vec![0u8; i]).collect::<LinkedList<_>>());
+        assert_eq!(c.dynamic_usage(), allocated);
+    }
+}
+
+#[test]
+fn vec_of_cstrings() {
+    use std::ffi::CString;
+
+    let args = ["ls", "-la", "/tmp", ""];
+    let (argv, allocated) = common::measure(|| {
+        let mut argv = Vec::with_capacity(args.len());
+        argv.extend(args.iter().map(|arg| CString::new(*arg).unwrap()));
+        argv
+    });
+
+    // The Vec's buffer, plus each string's bytes and its trailing nul byte.
+    let expected = args.len() * std::mem::size_of::<CString>()
+        + args.iter().map(|arg| arg.len() + 1).sum::<usize>();
+    assert_eq!(argv.dynamic_usage(), expected);
+    assert_eq!(argv.dynamic_usage_bounds(), (expected, Some(expected)));
+    assert_eq!(argv.dynamic_usage(), allocated);
+}
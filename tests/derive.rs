@@ -0,0 +1,263 @@
+//! Checks the impls generated by `#[derive(DynamicUsage)]`.
+
+#![cfg(feature = "derive")]
+
+use std::collections::HashMap;
+
+use memuse::DynamicUsage;
+
+#[derive(DynamicUsage)]
+struct Named {
+    id: u64,
+    tags: Vec<String>,
+}
+
+#[derive(DynamicUsage)]
+struct Tuple(String, Vec<u32>);
+
+#[derive(DynamicUsage)]
+struct Unit;
+
+#[derive(DynamicUsage)]
+struct Generic<T> {
+    items: Vec<T>,
+}
+
+#[derive(DynamicUsage)]
+struct Bounded<'a, T: 'a>
+where
+    T: Clone,
+{
+    items: Vec<T>,
+    _lifetime: std::marker::PhantomData<&'a T>,
+}
+
+#[derive(DynamicUsage)]
+enum Either<T: std::fmt::Debug, U = String>
+where
+    Vec<U>: Clone,
+{
+    Left(T),
+    Right(Vec<U>),
+}
+
+#[derive(DynamicUsage)]
+struct Marker<T> {
+    id: u32,
+    _marker: std::marker::PhantomData<T>,
+}
+
+#[derive(DynamicUsage)]
+struct RawIdent {
+    r#type: String,
+}
+
+#[derive(DynamicUsage)]
+struct View<'a> {
+    #[memuse(skip)]
+    name: &'a str,
+    #[memuse(skip)]
+    source: &'a Vec<u8>,
+    owned: Vec<u8>,
+}
+
+#[derive(DynamicUsage)]
+enum Borrowed<'a> {
+    Slice(#[memuse(skip)] &'a [u8], Vec<u8>),
+    Only(#[memuse(skip)] &'a mut Vec<u8>),
+}
+
+#[derive(DynamicUsage)]
+enum Message {
+    Ping,
+    Data(Vec<u8>),
+    Headers {
+        names: Vec<String>,
+        values: HashMap<u8, u8>,
+    },
+}
+
+#[derive(DynamicUsage)]
+enum Empty {}
+
+#[test]
+fn named_struct() {
+    let a = Named {
+        id: 7,
+        tags: vec![String::with_capacity(5), String::with_capacity(10)],
+    };
+    assert_eq!(a.id.dynamic_usage(), 0);
+    let expected = 2 * std::mem::size_of::<String>() + 15;
+    assert_eq!(a.dynamic_usage(), expected);
+    assert_eq!(a.dynamic_usage_bounds(), (expected, Some(expected)));
+    assert_eq!(a.try_dynamic_usage(), Some(expected));
+    assert!(!a.may_overcount());
+}
+
+#[test]
+fn tuple_struct() {
+    let a = Tuple(String::with_capacity(3), Vec::with_capacity(4));
+    assert_eq!(a.dynamic_usage(), 3 + 16);
+    assert_eq!(a.dynamic_usage_bounds(), (19, Some(19)));
+}
+
+#[test]
+fn unit_struct() {
+    assert_eq!(Unit.dynamic_usage(), 0);
+    assert_eq!(Unit.dynamic_usage_bounds(), (0, Some(0)));
+}
+
+#[test]
+fn generic_struct() {
+    let a = Generic {
+        items: vec![vec![0u8; 10], vec![0u8; 20]],
+    };
+    assert_eq!(a.dynamic_usage(), a.items.dynamic_usage());
+    assert_eq!(a.dynamic_usage_bounds(), a.items.dynamic_usage_bounds());
+
+    // A value whose usage may include allocations that are also counted elsewhere.
+    struct Overcounting;
+    impl DynamicUsage for Overcounting {
+        fn dynamic_usage(&self) -> usize {
+            0
+        }
+
+        fn dynamic_usage_bounds(&self) -> (usize, Option<usize>) {
+            (0, Some(0))
+        }
+
+        fn may_overcount(&self) -> bool {
+            true
+        }
+    }
+
+    let b = Generic {
+        items: vec![Overcounting],
+    };
+    assert!(b.may_overcount());
+}
+
+#[test]
+fn generics_with_bounds() {
+    let a = Bounded {
+        items: vec![String::with_capacity(5), String::with_capacity(7)],
+        _lifetime: std::marker::PhantomData,
+    };
+    let expected = 2 * std::mem::size_of::<String>() + 12;
+    assert_eq!(a.dynamic_usage(), expected);
+    assert_eq!(a.dynamic_usage_bounds(), (expected, Some(expected)));
+
+    let b: Either<Vec<u8>> = Either::Left(vec![0; 10]);
+    assert_eq!(b.dynamic_usage(), 10);
+    assert_eq!(b.dynamic_usage_bounds(), (10, Some(10)));
+
+    let c: Either<u8, Vec<u8>> = Either::Right(vec![vec![0; 3]; 2]);
+    let expected = 2 * std::mem::size_of::<Vec<u8>>() + 6;
+    assert_eq!(c.dynamic_usage(), expected);
+    assert_eq!(c.try_dynamic_usage(), Some(expected));
+}
+
+#[test]
+fn raw_ident() {
+    let a = RawIdent {
+        r#type: String::with_capacity(8),
+    };
+    assert_eq!(a.dynamic_usage(), 8);
+}
+
+#[test]
+fn enum_variants() {
+    let a = Message::Ping;
+    assert_eq!(a.dynamic_usage(), 0);
+    assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+
+    let b = Message::Data(vec![0; 100]);
+    assert_eq!(b.dynamic_usage(), 100);
+    assert_eq!(b.dynamic_usage_bounds(), (100, Some(100)));
+
+    let names = vec![String::from("host")];
+    let values = HashMap::with_capacity(10);
+    let expected = names.dynamic_usage() + values.dynamic_usage();
+    let expected_lower = names.dynamic_usage_bounds().0 + values.dynamic_usage_bounds().0;
+    let c = Message::Headers { names, values };
+    assert_eq!(c.dynamic_usage(), expected);
+    // The HashMap has no upper bound, so neither does the variant.
+    assert_eq!(c.dynamic_usage_bounds(), (expected_lower, None));
+}
+
+#[test]
+fn empty_enum() {
+    fn assert_impl<T: DynamicUsage>() {}
+    assert_impl::<Empty>();
+}
+
+#[test]
+fn dedup() {
+    use std::rc::Rc;
+
+    use memuse::Visited;
+
+    let shared = Rc::new(vec![0u8; 100]);
+    let a = Generic {
+        items: vec![shared.clone(), shared.clone()],
+    };
+    let b = Message::Data(vec![0; 10]);
+
+    let mut visited = Visited::new();
+    let usage = a.dedup_dynamic_usage(&mut visited);
+    assert_eq!(usage, a.items.dedup_dynamic_usage(&mut Visited::new()));
+    assert_eq!(visited.len(), 1);
+    assert_eq!(b.dedup_dynamic_usage(&mut visited), 10);
+}
+
+#[test]
+fn phantom_field() {
+    let a = Marker::<Vec<u8>> {
+        id: 1,
+        _marker: std::marker::PhantomData,
+    };
+    assert_eq!(a.id, 1);
+    assert_eq!(a.dynamic_usage(), 0);
+    assert_eq!(a.dynamic_usage_bounds(), (0, Some(0)));
+}
+
+#[test]
+fn borrowed_fields() {
+    let source = vec![0u8; 100];
+    let a = View {
+        name: "view",
+        source: &source,
+        owned: Vec::with_capacity(10),
+    };
+    // Only the owned field is counted.
+    assert_eq!(a.dynamic_usage(), 10);
+    assert_eq!(a.dynamic_usage_bounds(), (10, Some(10)));
+    assert_eq!(a.name.len() + a.source.len(), 104);
+    let b = Borrowed::Slice(&source, Vec::with_capacity(5));
+    assert_eq!(b.dynamic_usage(), 5);
+    assert_eq!(b.try_dynamic_usage(), Some(5));
+    if let Borrowed::Slice(borrowed, _) = b {
+        assert_eq!(borrowed.len(), 100);
+    }
+
+    let mut target = vec![0u8; 20];
+    let c = Borrowed::Only(&mut target);
+    assert_eq!(c.dynamic_usage(), 0);
+    assert_eq!(c.dynamic_usage_bounds(), (0, Some(0)));
+    if let Borrowed::Only(borrowed) = c {
+        borrowed.push(1);
+    }
+    assert_eq!(target.len(), 21);
+}
+
+#[test]
+fn dynamic_usage_used() {
+    let mut tags = Vec::with_capacity(4);
+    tags.push(String::from("a"));
+    let a = Named { id: 7, tags };
+    assert_eq!(a.dynamic_usage_used(), std::mem::size_of::<String>() + 1,);
+    assert_eq!(
+        Message::Data(Vec::with_capacity(10)).dynamic_usage_used(),
+        0,
+    );
+}
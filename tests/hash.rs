@@ -0,0 +1,250 @@
+//! Checks the `HashMap` and `HashSet` estimates against the allocations they make.
+
+#![cfg(feature = "std")]
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+use memuse::DynamicUsage;
+
+mod common;
+
+#[global_allocator]
+static ALLOCATOR: common::TrackingAllocator = common::TrackingAllocator;
+
+#[test]
+fn lifecycle() {
+    for capacity in [0, 1, 3, 4, 7, 8, 14, 15, 100, 1000, 12345].iter().copied() {
+        let (mut map, allocated) = common::measure(|| HashMap::<u64, u32>::with_capacity(capacity));
+        assert_eq!(map.dynamic_usage(), allocated);
+
+        // Filling the map within its capacity doesn't reallocate.
+        map.extend((0..capacity as u64).map(|i| (i, 0)));
+        assert_eq!(map.dynamic_usage(), allocated);
+
+        // Clearing the map retains its allocation.
+        map.clear();
+        assert_eq!(map.dynamic_usage(), allocated);
+    }
+}
+
+#[test]
+fn insertion_api_agnostic() {
+    for entries in [1u64, 10, 100, 1000, 10_000].iter().copied() {
+        let (by_insert, allocated) = common::measure(|| {
+            let mut map = HashMap::new();
+            for i in 0..entries {
+                map.insert(i, i);
+            }
+            map
+        });
+        assert_eq!(by_insert.dynamic_usage(), allocated);
+
+        let (by_entry, allocated) = common::measure(|| {
+            let mut map = HashMap::new();
+            for i in 0..entries {
+                map.entry(i).or_insert(i);
+            }
+            map
+        });
+        assert_eq!(by_entry.dynamic_usage(), allocated);
+
+        let (by_collect, allocated) =
+            common::measure(|| (0..entries).map(|i| (i, i)).collect::<HashMap<_, _>>());
+        assert_eq!(by_collect.dynamic_usage(), allocated);
+
+        assert_eq!(by_insert.dynamic_usage(), by_entry.dynamic_usage());
+        assert_eq!(by_insert.dynamic_usage(), by_collect.dynamic_usage());
+
+        #[cfg(feature = "hashbrown")]
+        {
+            use std::collections::hash_map::RandomState;
+
+            let (by_raw_entry, allocated) = common::measure(|| {
+                let mut map = hashbrown::HashMap::with_hasher(RandomState::new());
+                for i in 0..entries {
+                    map.raw_entry_mut().from_key(&i).or_insert(i, i);
+                }
+                map
+            });
+            assert_eq!(by_raw_entry.dynamic_usage(), allocated);
+            assert_eq!(by_insert.dynamic_usage(), by_raw_entry.dynamic_usage());
+        }
+    }
+}
+
+#[test]
+fn over_aligned_values() {
+    #[allow(dead_code)]
+    #[repr(align(64))]
+    struct CacheLineAligned(u64);
+    memuse::impl_no_dynamic_usage!(CacheLineAligned);
+
+    // The alignment padding is part of the bucket size.
+    assert_eq!(std::mem::size_of::<(u64, CacheLineAligned)>(), 128);
+
+    for capacity in [1, 3, 7, 8, 100, 1000].iter().copied() {
+        let (mut map, allocated) =
+            common::measure(|| HashMap::<u64, CacheLineAligned>::with_capacity(capacity));
+        assert_eq!(map.dynamic_usage(), allocated);
+
+        map.extend((0..capacity as u64).map(|i| (i, CacheLineAligned(i))));
+        assert_eq!(map.dynamic_usage(), allocated);
+    }
+}
+
+#[test]
+fn removals() {
+    // Removing entries leaves tombstones that reduce the map's capacity without freeing
+    // its buckets, so the estimate (inferred from the capacity) is only a lower bound.
+    let (map, allocated) = common::measure(|| {
+        let mut map = HashMap::<u64, u64>::with_capacity(100);
+        for i in 0..10_000 {
+            map.insert(i, i);
+            if i >= 50 {
+                map.remove(&(i - 50));
+            }
+        }
+        map
+    });
+    let (lower, upper) = map.dynamic_usage_bounds();
+    assert!(lower <= allocated);
+    assert!(map.dynamic_usage() <= allocated);
+    assert_eq!(upper, None);
+}
+
+/// Checks the estimate for a map with an allocating value type, using the given hasher.
+fn check_with_hasher<S: BuildHasher + Default + DynamicUsage>() {
+    for entries in [0, 1, 7, 100, 1000].iter().copied() {
+        let (map, allocated) = common::measure(|| {
+            let mut map = HashMap::<u64, Vec<u8>, S>::default();
+            for i in 0..entries {
+                map.insert(i, vec![0; i as usize % 50]);
+            }
+            map
+        });
+        assert_eq!(map.dynamic_usage(), allocated);
+    }
+}
+
+#[test]
+fn fxhash() {
+    // fxhash::FxBuildHasher is BuildHasherDefault<FxHasher>.
+    check_with_hasher::<fxhash::FxBuildHasher>();
+}
+
+#[cfg(feature = "rustc-hash")]
+#[test]
+fn rustc_hash() {
+    check_with_hasher::<rustc_hash::FxBuildHasher>();
+}
+
+#[cfg(feature = "hashbrown")]
+#[test]
+fn hashbrown_lifecycle() {
+    use std::collections::hash_map::RandomState;
+
+    for capacity in [0, 1, 3, 4, 7, 8, 14, 15, 100, 1000, 12345].iter().copied() {
+        let (mut map, allocated) = common::measure(|| {
+            hashbrown::HashMap::<u64, u32, _>::with_capacity_and_hasher(
+                capacity,
+                RandomState::new(),
+            )
+        });
+        assert_eq!(map.dynamic_usage(), allocated);
+
+        map.extend((0..capacity as u64).map(|i| (i, 0)));
+        assert_eq!(map.dynamic_usage(), allocated);
+    }
+}
+
+#[cfg(feature = "indexmap")]
+#[test]
+fn indexmap_lifecycle() {
+    use std::collections::hash_map::RandomState;
+
+    // Capacities that hashbrown's index table can hold exactly, so that the entries
+    // `Vec` has the same capacity as the table.
+    for capacity in [0, 3, 7, 14, 28, 112, 896].iter().copied() {
+        let (mut map, allocated) = common::measure(|| {
+            indexmap::IndexMap::<u64, u32, _>::with_capacity_and_hasher(
+                capacity,
+                RandomState::new(),
+            )
+        });
+        assert_eq!(map.dynamic_usage(), allocated);
+        assert!(map.dynamic_usage_bounds().0 <= allocated);
+
+        map.extend((0..capacity as u64).map(|i| (i, 0)));
+        assert_eq!(map.dynamic_usage(), allocated);
+        assert!(map.dynamic_usage_bounds().0 <= allocated);
+
+        let (set, allocated) = common::measure(|| {
+            let mut set =
+                indexmap::IndexSet::with_capacity_and_hasher(capacity, RandomState::new());
+            set.extend((0..capacity).map(|i| vec![0u8; i % 7]));
+            set
+        });
+        assert_eq!(set.dynamic_usage(), allocated);
+        assert!(set.dynamic_usage_bounds().0 <= allocated);
+    }
+}
+
+#[cfg(feature = "indexmap")]
+#[test]
+fn indexmap_bounds() {
+    use std::collections::hash_map::RandomState;
+
+    for entries in [1u64, 10, 100, 1000, 10_000].iter().copied() {
+        let (map, allocated) = common::measure(|| {
+            let mut map = indexmap::IndexMap::with_hasher(RandomState::new());
+            for i in 0..entries {
+                map.insert(i, String::with_capacity(i as usize % 13));
+            }
+            map
+        });
+        assert!(map.dynamic_usage_bounds().0 <= allocated);
+        assert_eq!(map.dynamic_usage(), allocated);
+        assert!(map.dynamic_usage_used() <= allocated);
+    }
+}
+
+#[cfg(feature = "dashmap")]
+#[test]
+fn dashmap() {
+    use std::collections::hash_map::RandomState;
+
+    for entries in [0u64, 1, 10, 100, 1000].iter().copied() {
+        let (map, allocated) = common::measure(|| {
+            let map = dashmap::DashMap::with_hasher(RandomState::new());
+            for i in 0..entries {
+                map.insert(i, vec![0u8; i as usize % 13]);
+            }
+            map
+        });
+        assert_eq!(map.dynamic_usage(), allocated);
+        assert!(map.dynamic_usage_bounds().0 <= allocated);
+        assert_eq!(map.try_dynamic_usage(), Some(allocated));
+        assert!(map.dynamic_usage_used() <= allocated);
+    }
+}
+
+#[cfg(feature = "dashmap")]
+#[test]
+fn dashmap_locked_shard() {
+    let map = dashmap::DashMap::new();
+    map.insert(1u64, vec![0u8; 100]);
+    let usage = map.dynamic_usage();
+
+    // Holding a mutable reference into the map locks the entry's shard. Measuring the
+    // map must not deadlock.
+    let entry = map.get_mut(&1).unwrap();
+    assert_eq!(map.try_dynamic_usage(), None);
+    assert!(map.dynamic_usage() < usage);
+    let (lower, upper) = map.dynamic_usage_bounds();
+    assert!(lower < usage);
+    assert_eq!(upper, None);
+    drop(entry);
+
+    assert_eq!(map.dynamic_usage(), usage);
+}
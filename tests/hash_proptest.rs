@@ -0,0 +1,71 @@
+//! Property tests checking the `HashMap` estimate against the allocations it makes, for
+//! arbitrary capacities, contents, and sequences of operations.
+
+#![cfg(feature = "std")]
+
+use std::collections::HashMap;
+
+use memuse::DynamicUsage;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+mod common;
+
+#[global_allocator]
+static ALLOCATOR: common::TrackingAllocator = common::TrackingAllocator;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(u64, usize),
+    Remove(u64),
+    ShrinkToFit,
+    Clear,
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        8 => (0..500u64, 0..64usize).prop_map(|(k, len)| Op::Insert(k, len)),
+        2 => (0..500u64).prop_map(Op::Remove),
+        1 => Just(Op::ShrinkToFit),
+        1 => Just(Op::Clear),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn bounds_and_allocations(capacity in 0..2000usize, ops in vec(op(), 0..300)) {
+        let removes = ops.iter().any(|op| matches!(op, Op::Remove(_)));
+
+        let (map, allocated) = common::measure(|| {
+            let mut map = HashMap::<u64, Vec<u8>>::with_capacity(capacity);
+            for op in &ops {
+                match *op {
+                    Op::Insert(k, len) => {
+                        map.insert(k, vec![0; len]);
+                    }
+                    Op::Remove(k) => {
+                        map.remove(&k);
+                    }
+                    Op::ShrinkToFit => map.shrink_to_fit(),
+                    Op::Clear => map.clear(),
+                }
+            }
+            map
+        });
+
+        let usage = map.dynamic_usage();
+        let (lower, upper) = map.dynamic_usage_bounds();
+        prop_assert!(lower <= usage);
+        if let Some(upper) = upper {
+            prop_assert!(usage <= upper);
+        }
+
+        if removes {
+            // Removals can leave tombstones that reduce the reported capacity below the
+            // number of buckets, so the estimate may undercount.
+            prop_assert!(usage <= allocated);
+        } else {
+            prop_assert_eq!(usage, allocated);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Checks the `serde_json::Value` estimates against the allocations they make.
+
+#![cfg(all(feature = "std", feature = "serde_json"))]
+
+use memuse::DynamicUsage;
+use serde_json::{json, Value};
+
+mod common;
+
+#[global_allocator]
+static ALLOCATOR: common::TrackingAllocator = common::TrackingAllocator;
+
+fn check_bounds(value: &Value, allocated: usize) {
+    let (lower, upper) = value.dynamic_usage_bounds();
+    assert!(
+        lower <= allocated,
+        "lower bound {} > allocated {}",
+        lower,
+        allocated
+    );
+    if let Some(upper) = upper {
+        assert!(
+            allocated <= upper,
+            "upper bound {} < allocated {}",
+            upper,
+            allocated
+        );
+    }
+}
+
+#[test]
+fn scalars() {
+    for value in [json!(null), json!(true), json!(7), json!(-1.5)].iter() {
+        assert_eq!(value.dynamic_usage(), 0);
+        assert_eq!(value.dynamic_usage_bounds(), (0, Some(0)));
+    }
+
+    let (value, allocated) = common::measure(|| json!("a string"));
+    assert_eq!(value.dynamic_usage(), allocated);
+    assert_eq!(value.dynamic_usage_bounds(), (allocated, Some(allocated)));
+}
+
+#[test]
+fn arrays() {
+    let (value, allocated) = common::measure(|| json!([1, "two", [3, "four"], null]));
+    assert_eq!(value.dynamic_usage(), allocated);
+    assert_eq!(value.dynamic_usage_bounds(), (allocated, Some(allocated)));
+}
+
+#[test]
+fn nested() {
+    for entries in [0, 1, 10, 100, 1000].iter().copied() {
+        let (value, allocated) = common::measure(|| {
+            let mut map = serde_json::Map::new();
+            for i in 0..entries {
+                map.insert(
+                    format!("key{}", i),
+                    json!({
+                        "id": i,
+                        "name": format!("item {}", i),
+                        "tags": ["a", "b", "c"],
+                    }),
+                );
+            }
+            json!({ "items": Value::Object(map), "count": entries })
+        });
+        check_bounds(&value, allocated);
+    }
+}